@@ -0,0 +1,206 @@
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::protocol::{listen, spawn_writer};
+use crate::terminal::*;
+use crate::terminal::Color::*;
+use crate::{check_ctrl_c, ctrl_c_channel, input_channel, move_for_key, Game, Move, DRAW_SPEED};
+
+const BROADCAST_HEADER: &str = "tetris-broadcast";
+const BROADCAST_VERSION: u32 = 1;
+
+/// Streams the game being played to whoever connects, a line of text per message:
+///
+/// ```text
+/// tetris-broadcast 1     (first thing on connecting)
+/// state                  (the whole game as saved, ended by an empty line, sent on joining and
+/// tetris-save 1           whenever the game doesn't just go on, as on restarting or undoing)
+/// ...
+///
+/// frame 50 lr            (the game played a frame that took these milliseconds and these moves in it,
+///                         - for none)
+/// pause 1                (the game was paused, 0 when it goes on)
+/// over                   (game over, after the state it ended in)
+/// ```
+///
+/// Watchers play the frames on their copy of the game, which keeps it the same as the one played.
+pub struct Broadcast {
+    joined: Receiver<TcpStream>,
+    // lines to send to each watcher, written on a thread per watcher
+    watchers: Vec<Sender<String>>,
+    // the game as watchers have it
+    sent_frame: u64,
+    sent_inputs: usize,
+    sent_paused: bool,
+}
+
+impl Broadcast {
+    pub fn start(port: u16) -> Result<Broadcast, String> {
+        let listener = listen(port)?;
+        let (joined_tx, joined) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                if joined_tx.send(stream).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Broadcast { joined, watchers: Vec::new(), sent_frame: 0, sent_inputs: 0, sent_paused: false })
+    }
+
+    /// Tells the watchers what happened in the game since the last update, new watchers get all of it.
+    pub fn update(&mut self, game: &Game, paused: bool) {
+        let inputs = game.inputs.len();
+        if game.frame == self.sent_frame + 1 && inputs >= self.sent_inputs {
+            let moves = match inputs == self.sent_inputs {
+                true => "-".to_string(),
+                false => game.inputs[self.sent_inputs..].iter().map(|(_, mov)| mov.code()).collect(),
+            };
+            // frames of the countdown don't count for the time
+            let millis = game.timing.last().copied().unwrap_or(DRAW_SPEED);
+            self.send(format!("frame {} {}", millis, moves));
+        } else if game.frame != self.sent_frame || inputs != self.sent_inputs {
+            self.send(state(game));
+        }
+        if paused != self.sent_paused {
+            self.send(format!("pause {}", paused as u8));
+        }
+        self.sent_frame = game.frame;
+        self.sent_inputs = inputs;
+        self.sent_paused = paused;
+
+        // new watchers start from the game as it is now
+        while let Ok(stream) = self.joined.try_recv() {
+            let (watcher, _) = spawn_writer(stream);
+            watcher.send(format!("{} {}\n", BROADCAST_HEADER, BROADCAST_VERSION)).ok();
+            watcher.send(format!("{}\n", state(game))).ok();
+            if paused {
+                watcher.send("pause 1\n".to_string()).ok();
+            }
+            self.watchers.push(watcher);
+        }
+    }
+
+    /// Shows the watchers how the game ended, they see the broadcast end when the player quit.
+    pub fn game_over(&mut self, game: &Game) {
+        self.send(state(game));
+        self.send("over".to_string());
+    }
+
+    fn send(&mut self, line: String) {
+        // the writer of a watcher who left is gone
+        let line = format!("{}\n", line);
+        self.watchers.retain(|watcher| watcher.send(line.clone()).is_ok());
+    }
+}
+
+fn state(game: &Game) -> String {
+    format!("state\n{}", game.snapshot())
+}
+
+/// A message of the broadcast, read off the connection.
+pub enum Update {
+    State(Box<Game>),
+    Frame(u64, Vec<Move>),
+    Paused(bool),
+    Over,
+}
+
+/// Connects to a broadcast, the updates come in on the channel as they are read.
+pub fn tune_in(address: &str) -> Result<Receiver<Result<Update, String>>, String> {
+    let stream = TcpStream::connect(address).map_err(|error| format!("cannot connect to {}: {}", address, error))?;
+    let mut lines = BufReader::new(stream).lines().map_while(Result::ok);
+    let header = lines.next().ok_or_else(|| format!("{} closed the connection", address))?;
+    match header.split_once(' ') {
+        Some((BROADCAST_HEADER, version)) if version.parse() == Ok(BROADCAST_VERSION) => {}
+        Some((BROADCAST_HEADER, version)) => return Err(format!(
+            "{} broadcasts with version {}, this is version {}", address, version, BROADCAST_VERSION)),
+        _ => return Err(format!("{} doesn't broadcast a game", address)),
+    }
+    let (updates_tx, updates) = channel();
+    thread::spawn(move || {
+        while let Some(line) = lines.next() {
+            let update = match line.as_str() {
+                "state" => {
+                    let snapshot = lines.by_ref().take_while(|line| !line.is_empty()).collect::<Vec<_>>().join("\n");
+                    Game::from_snapshot(&snapshot).map(|game| Update::State(Box::new(game)))
+                }
+                "over" => Ok(Update::Over),
+                _ => read_update(&line),
+            };
+            if updates_tx.send(update).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(updates)
+}
+
+fn read_update(line: &str) -> Result<Update, String> {
+    let invalid = || format!("invalid broadcast: {}", line);
+    match line.split_once(' ').ok_or_else(invalid)? {
+        ("frame", frame) => {
+            let (millis, codes) = frame.split_once(' ').ok_or_else(invalid)?;
+            let millis = millis.parse().map_err(|_| invalid())?;
+            let moves = match codes {
+                "-" => Vec::new(),
+                codes => codes.chars().map(|code| Move::from_code(code).ok_or_else(invalid)).collect::<Result<_, _>>()?,
+            };
+            Ok(Update::Frame(millis, moves))
+        }
+        ("pause", paused) => Ok(Update::Paused(paused == "1")),
+        _ => Err(invalid()),
+    }
+}
+
+/// Shows the broadcast game as it's played, until the broadcast ends or the watcher quits with q.
+pub fn watch(address: &str, updates: Receiver<Result<Update, String>>) {
+    let input_rx = input_channel(move_for_key);
+    let ctrl_c_rx = ctrl_c_channel();
+    line_in_color(&format!("Watching {}, q to stop", address), YELLOW);
+    create_game_screen(24);
+    let (mut game, mut paused, mut over) = (None, false, false);
+    loop {
+        if check_ctrl_c(&ctrl_c_rx) || input_rx.try_iter().any(|mov| mov == Move::Quit) {
+            break;
+        }
+        let first = match updates.recv_timeout(Duration::from_millis(DRAW_SPEED)) {
+            Ok(update) => update,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                line_in_color("The broadcast ended", BLUE);
+                break;
+            }
+        };
+        // draws once for the updates that piled up
+        for update in [first].into_iter().chain(updates.try_iter()) {
+            match update {
+                Ok(Update::State(state)) => {
+                    game = Some(*state);
+                    over = false;
+                }
+                Ok(Update::Frame(millis, moves)) => if let Some(game) = &mut game {
+                    game.timed_tick(millis);
+                    for mov in moves {
+                        game.apply(mov);
+                    }
+                },
+                Ok(Update::Paused(now)) => paused = now,
+                Ok(Update::Over) => over = true,
+                Err(error) => {
+                    line_in_color(&error, RED);
+                    return;
+                }
+            }
+        }
+        match &game {
+            Some(game) if over => game.draw_with_messages(&[(10, blink!("GAME OVER!", RED))]),
+            Some(game) if paused => game.draw_paused(),
+            Some(game) => game.draw(),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use crate::modes::Mode;
+use crate::{Block, Coord, Game, BOARD_HEIGHT, BOARD_WIDTH};
+
+pub const FINESSE_TARGETS: u32 = 20;
+
+/// Cells of a block from left to right, the same for blocks covering the same cells in any order.
+fn position(block: &Block) -> [(usize, usize); 4] {
+    let mut position = cells(block).map(|coord| (coord.x, coord.y));
+    position.sort();
+    position
+}
+
+/// The position lifted so the block's top is in the first row,
+/// blocks in the same column and orientation have the same shape wherever they are.
+fn shape(block: &Block) -> [(usize, usize); 4] {
+    let top = cells(block).iter().map(|coord| coord.y).min().expect("blocks have cells");
+    position(block).map(|(x, y)| (x, y - top))
+}
+
+fn cells(block: &Block) -> [Coord; 4] {
+    [block.a, block.b, block.c, block.d]
+}
+
+impl Game {
+    /// The fewest turns and moves that bring the block in play from where it appeared to every
+    /// column and orientation, with the block as it is there.
+    fn finesse_paths(&self) -> Vec<(Block, u32)> {
+        let on_board = |block: &Block| cells(block).iter().all(|coord| coord.x < self.width() && coord.y < self.height());
+        let spawned = self.centered(Self::spawn_form(self.block));
+        let mut paths = vec![(spawned, 0)];
+        let mut queue = VecDeque::from([(spawned, 0)]);
+        while let Some((block, keys)) = queue.pop_front() {
+            let mut turned = block;
+            turned.turn(self.width(), self.height());
+            let (mut left, mut right) = (block, block);
+            let moved = [Some(turned), left.move_left().then_some(left), right.move_right().then_some(right)];
+            for next in moved.into_iter().flatten().filter(on_board) {
+                if paths.iter().all(|(known, _)| shape(known) != shape(&next)) {
+                    paths.push((next, keys + 1));
+                    queue.push_back((next, keys + 1));
+                }
+            }
+        }
+        paths
+    }
+
+    /// Where a block ends up dropping straight down from where it is.
+    fn dropped(&self, mut block: Block) -> Block {
+        loop {
+            let mut below = block;
+            if !below.move_down() || !cells(&below).iter().all(|coord| self.check_free(coord)) {
+                return block;
+            }
+            block = below;
+        }
+    }
+
+    /// Counts a fault when the block in play took more turns and moves than it needed to get where it
+    /// is locking, or missed the target of a drill. Blocks tucked or spun under the stack aren't judged,
+    /// dropping straight down doesn't get them there.
+    pub fn judge_finesse(&mut self) {
+        let keys = std::mem::take(&mut self.finesse_keys);
+        let missed = self.target.is_some_and(|target| position(&target) != position(&self.block));
+        let wasted = self.finesse_paths().into_iter()
+            .find(|(block, _)| shape(block) == shape(&self.block))
+            .filter(|(block, _)| position(&self.dropped(*block)) == position(&self.block))
+            .is_some_and(|(_, fewest)| keys > fewest);
+        if missed || wasted {
+            self.finesse_faults += 1;
+        }
+    }
+
+    /// Clears the board of a drill for the next block and picks where it should go.
+    pub fn next_target(&mut self) {
+        if !matches!(self.rules.mode, Mode::Finesse { .. }) {
+            return;
+        }
+        self.board = [[0; BOARD_HEIGHT]; BOARD_WIDTH];
+        let paths = self.finesse_paths();
+        let (block, _) = paths[self.rng.gen_range(0..paths.len())];
+        self.target = Some(self.dropped(block));
+    }
+}
@@ -0,0 +1,223 @@
+use rand::Rng;
+
+use crate::random::Randomizer;
+use crate::{Game, DRAW_SPEED, SPAWN_ROWS};
+
+/// Board value of a garbage cell, set apart from the colors of the blocks.
+pub const GARBAGE: u8 = 8;
+// chance the hole moves between the batches of garbage an opponent sends
+const ATTACK_MESSINESS: u32 = 30;
+// frames sent garbage waits before it can rise, the time there is to cancel it
+pub const GARBAGE_DELAY: u64 = 1000 / DRAW_SPEED;
+// rows of garbage sent for clearing 0 to 4 lines at once, T-spins send more for their lines
+const ATTACK: [u32; 5] = [0, 0, 1, 2, 4];
+const TSPIN_ATTACK: [u32; 4] = [0, 2, 4, 6];
+// rows added for every clear in a row after the first, the last number holds for longer combos
+const COMBO_ATTACK: [u32; 11] = [0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+// rows added for a tetris or T-spin following another with no easier clear between them
+const BACK_TO_BACK_ATTACK: u32 = 1;
+const PERFECT_CLEAR_ATTACK: u32 = 10;
+// sets the generator of garbage holes apart from the one of the blocks
+const GARBAGE_STREAM: u64 = 0x5851f42d4c957f2d;
+
+/// Generator for the holes of garbage sent by opponents, forked from the game's generator for `seed`.
+/// Drawing them from the game's generator would deal a player who got garbage other blocks than
+/// the opponents, who play the same seed.
+pub fn garbage_randomizer(seed: u64) -> Randomizer {
+    Randomizer::new(seed).fork(GARBAGE_STREAM)
+}
+
+/// Garbage an opponent sent, the rows rise together once the frame it's ready in has come.
+#[derive(Copy, Clone, Debug)]
+pub struct Batch {
+    pub rows: u32,
+    pub ready: u64,
+}
+
+/// Where the holes of garbage sent by opponents go, the hole moves from the last one with
+/// a chance of the given percent, between batches or between every row.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Holes {
+    Batch(u32),
+    Row(u32),
+}
+
+impl Default for Holes {
+    fn default() -> Holes {
+        Holes::Batch(ATTACK_MESSINESS)
+    }
+}
+
+impl Holes {
+    /// Reads `batch` or `row`, optionally with the percent after a colon as in `row:50`.
+    pub fn parse(text: &str) -> Result<Holes, String> {
+        let invalid = || format!("invalid holes: {}, use batch or row with an optional :<percent>", text);
+        let (style, messiness) = match text.split_once(':') {
+            Some((style, percent)) => (style, Some(percent.parse::<u32>().ok().filter(|&p| p <= 100).ok_or_else(invalid)?)),
+            None => (text, None),
+        };
+        match style {
+            "batch" => Ok(Holes::Batch(messiness.unwrap_or(ATTACK_MESSINESS))),
+            "row" => Ok(Holes::Row(messiness.unwrap_or(100))),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn messiness(&self) -> u32 {
+        match self {
+            Holes::Batch(messiness) | Holes::Row(messiness) => *messiness,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        match self {
+            Holes::Batch(messiness) => format!("batch:{}", messiness),
+            Holes::Row(messiness) => format!("row:{}", messiness),
+        }
+    }
+}
+
+impl Game {
+    /// Pushes the board up by a row of garbage with a hole in column `hole`.
+    pub fn insert_garbage_row(&mut self, hole: usize) {
+        let bottom = self.height() - 1;
+        for x in 0..self.width() {
+            self.board[x].copy_within(1..=bottom, 0);
+            self.board[x][bottom] = if x == hole { 0 } else { GARBAGE };
+            self.placed[x].copy_within(1..=bottom, 0);
+            self.placed[x][bottom] = self.frame;
+        }
+    }
+
+    /// Pushes the board up by a row of garbage like `insert_garbage_row`, moving the block in play up
+    /// with the stack when it's in the way. Returns false when the stack or the block is pushed over the top.
+    pub fn raise_garbage(&mut self, hole: usize) -> bool {
+        let topped_out = (0..self.width()).any(|x| self.board[x][0] != 0);
+        self.insert_garbage_row(hole);
+        if self.spawning() || self.legal_move() {
+            return !topped_out;
+        }
+        // the stack under the block moved up a row, the block takes the same place above it
+        if [self.block.a, self.block.b, self.block.c, self.block.d].iter().any(|coord| coord.y == 0) {
+            return false;
+        }
+        self.block.move_up();
+        !topped_out && self.legal_move()
+    }
+
+    /// Rows of garbage the last placement sends, counting the combo and back-to-back clears it goes on with.
+    fn attack(&mut self) -> u32 {
+        let clear = self.last_clear;
+        if clear.lines == 0 {
+            self.combo = 0;
+            return 0;
+        }
+        let lines = clear.lines as usize;
+        let mut attack = match clear.tspin {
+            true => TSPIN_ATTACK[lines.min(TSPIN_ATTACK.len() - 1)],
+            false => ATTACK[lines.min(ATTACK.len() - 1)],
+        };
+        attack += COMBO_ATTACK[(self.combo as usize).min(COMBO_ATTACK.len() - 1)];
+        self.combo += 1;
+        let difficult = clear.tspin || clear.lines >= 4;
+        if difficult && self.back_to_back {
+            attack += BACK_TO_BACK_ATTACK;
+        }
+        self.back_to_back = difficult;
+        if clear.perfect {
+            attack += PERFECT_CLEAR_ATTACK;
+        }
+        attack
+    }
+
+    /// Queues garbage an opponent sent, it can be cancelled until it's ready to rise.
+    pub fn receive_garbage(&mut self, rows: u32) {
+        if rows > 0 {
+            self.incoming.push(Batch { rows, ready: self.frame + GARBAGE_DELAY });
+        }
+    }
+
+    /// Rows of garbage waiting to rise, and the ones among them that are ready to.
+    pub fn incoming_rows(&self) -> u32 {
+        self.incoming.iter().map(|batch| batch.rows).sum()
+    }
+
+    pub fn ready_rows(&self) -> u32 {
+        self.incoming.iter().filter(|batch| batch.ready <= self.frame).map(|batch| batch.rows).sum()
+    }
+
+    /// Sends garbage for the lines the last placement cleared, cancelling the garbage waiting to rise
+    /// first, the oldest before the rest. A placement that clears nothing lets the garbage that is ready rise,
+    /// returns false when it pushed the stack over the top.
+    pub fn trade_garbage(&mut self) -> bool {
+        let mut attack = self.attack();
+        while attack > 0 && !self.incoming.is_empty() {
+            let cancelled = attack.min(self.incoming[0].rows);
+            attack -= cancelled;
+            self.incoming[0].rows -= cancelled;
+            if self.incoming[0].rows == 0 {
+                self.incoming.remove(0);
+            }
+        }
+        self.outgoing += attack;
+        if self.last_clear.lines > 0 {
+            return true;
+        }
+        let frame = self.frame;
+        let (ready, waiting) = self.incoming.iter().partition::<Vec<Batch>, _>(|batch| batch.ready <= frame);
+        self.incoming = waiting;
+        let holes = self.rules.holes;
+        for batch in ready {
+            let mut hole = self.attack_hole(holes.messiness());
+            for row in 0..batch.rows {
+                if row > 0 && matches!(holes, Holes::Row(_)) {
+                    hole = self.attack_hole(holes.messiness());
+                }
+                if !self.raise_garbage(hole) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Column of the hole in the next garbage row, moving from the last one with a chance of
+    /// `messiness` percent so a clean setting digs down a single well.
+    pub fn next_hole(&mut self, messiness: u32) -> usize {
+        self.hole = moved_hole(self.hole, self.width(), messiness, &mut self.rng);
+        self.hole
+    }
+
+    /// Like `next_hole` for garbage sent by opponents, which has a generator of its own.
+    fn attack_hole(&mut self, messiness: u32) -> usize {
+        self.hole = moved_hole(self.hole, self.width(), messiness, &mut self.garbage_rng);
+        self.hole
+    }
+
+    /// Rows on the board that still have garbage in them.
+    pub fn garbage_rows(&self) -> u32 {
+        (0..self.height()).filter(|&y| (0..self.width()).any(|x| self.board[x][y] == GARBAGE)).count() as u32
+    }
+
+    /// Raises garbage that is still to come until `visible` rows of it are on the board.
+    /// A big board keeps the rows blocks appear in clear of garbage as well.
+    /// Returns false when the stack is pushed over the top.
+    pub fn fill_garbage(&mut self, visible: u32, messiness: u32) -> bool {
+        let visible = visible.min((self.height() - SPAWN_ROWS) as u32);
+        while self.garbage_left > 0 && self.garbage_rows() < visible {
+            let hole = self.next_hole(messiness);
+            self.garbage_left -= 1;
+            if !self.raise_garbage(hole) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn moved_hole(hole: usize, width: usize, messiness: u32, rng: &mut Randomizer) -> usize {
+    match hole >= width || rng.gen_range(0..100) < messiness {
+        true => rng.gen_range(0..width),
+        false => hole,
+    }
+}
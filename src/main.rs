@@ -1,6 +1,6 @@
 extern crate core;
 
-use std::{thread};
+use std::{fs, mem, process, thread};
 use std::io;
 use std::io::Read;
 use std::io::Write;
@@ -13,18 +13,87 @@ use rand::Rng;
 use terminal::*;
 
 use crate::Move::*;
+use crate::broadcast::{tune_in, watch, Broadcast};
+use crate::garbage::{garbage_randomizer, Batch, GARBAGE};
+use crate::master::grade;
+use crate::modes::{format_time, show_results, show_splits, Mode};
+use crate::online::{host, join, play_online, Session};
+use crate::options::{Options, USAGE};
+use crate::pacer::{Pacer, Stopwatch};
+use crate::practice::Practice;
+use crate::protocol::listen;
+use crate::puzzle::{load_pack, play_puzzles};
+use crate::random::{random_seed, Randomizer};
+use crate::replay::{load_replay, play_replay, save_replay};
+use crate::royale::Royale;
+use crate::rules::Rules;
+use crate::sandbox::play_sandbox;
+use crate::save::{default_save_path, load_game, save_game};
+use crate::server::serve;
+use crate::verify::verify;
+use crate::versus::{play_versus, VERSUS_ROUNDS};
 use crate::terminal::Color;
 use crate::terminal::Color::*;
 
 #[macro_use]
 mod terminal;
-
-#[derive(Debug)]
+mod broadcast;
+mod finesse;
+mod garbage;
+mod master;
+mod modes;
+mod online;
+mod options;
+mod pacer;
+mod practice;
+mod protocol;
+mod puzzle;
+mod random;
+mod records;
+mod replay;
+mod royale;
+mod rules;
+mod sandbox;
+mod save;
+mod server;
+mod survival;
+mod versus;
+mod verify;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 enum Move {
     Up,
     Down,
     Right,
     Left,
+    Hold,
+    Pause,
+    FocusLost,
+    Restart,
+    Quit,
+    Undo,
+    Redo,
+    Edit,
+}
+
+// moves that play the game, as recorded in replays
+const GAME_MOVES: [(Move, char); 5] = [(Up, 'u'), (Down, 'd'), (Right, 'r'), (Left, 'l'), (Hold, 'h')];
+
+impl Move {
+    pub fn code(&self) -> char {
+        GAME_MOVES.iter()
+            .find(|(mov, _)| mov == self)
+            .map(|(_, code)| *code)
+            .expect("only game moves have a code")
+    }
+
+    pub fn from_code(code: char) -> Option<Move> {
+        GAME_MOVES.iter().find(|(_, c)| *c == code).map(|(mov, _)| *mov)
+    }
+
+    pub fn plays_game(&self) -> bool {
+        GAME_MOVES.iter().any(|(mov, _)| mov == self)
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -43,7 +112,8 @@ struct Block {
 }
 
 impl Block {
-    pub fn turn(&mut self) {
+    /// Turns clockwise, blocks against the right wall or the floor of a `width` by `height` board move away from it.
+    pub fn turn(&mut self, width: usize, height: usize) {
         match self.color {
             RED => {
                 if self.a.x != self.b.x {
@@ -56,7 +126,7 @@ impl Block {
                     // A
                     // BC
                     //  D
-                    let delta_x = if self.d.x == BOARD_WIDTH - 1 { -1i8 } else { 0 };
+                    let delta_x = if self.d.x == width - 1 { -1i8 } else { 0 };
                     self.a.y += 2;
                     self.a.x = (self.a.x as i8 + delta_x) as usize;
                     self.b.x += (1 + delta_x) as usize;
@@ -67,7 +137,7 @@ impl Block {
             GREEN => {
                 if self.a.x != self.b.x {
                     // ABCD
-                    let delta_y = if self.d.y == BOARD_HEIGHT - 1 { -1 } else { 0 };
+                    let delta_y = if self.d.y == height - 1 { -1 } else { 0 };
                     self.a.x = self.c.x;
                     self.a.y = (self.a.y as i8 - 2 + delta_y) as usize;
 
@@ -79,7 +149,7 @@ impl Block {
                     self.d.x = self.c.x;
                     self.d.y = (self.d.y as i8 + 1 + delta_y) as usize;
                 } else {
-                    let delta_x = if self.d.x == BOARD_WIDTH - 1 { -1 } else { (0.min(self.d.x as i8 - 2)).abs() };
+                    let delta_x = if self.d.x == width - 1 { -1 } else { (0.min(self.d.x as i8 - 2)).abs() };
                     self.a.x = (self.a.x as i8 - 2 + delta_x) as usize;
                     self.a.y = self.c.y;
 
@@ -102,7 +172,7 @@ impl Block {
                     //  A
                     // BC
                     // D
-                    let delta_x = if self.c.x == BOARD_WIDTH - 1 { -1i8 } else { 0 };
+                    let delta_x = if self.c.x == width - 1 { -1i8 } else { 0 };
                     self.a.y += 2;
                     self.a.x = (self.a.x as i8 + delta_x) as usize;
                     self.b.x = (self.b.x as i8 + delta_x) as usize;
@@ -115,7 +185,7 @@ impl Block {
                     // A
                     // B
                     // CD
-                    let delta_x = if self.d.x == BOARD_WIDTH - 1 { -1i8 } else { 0 };
+                    let delta_x = if self.d.x == width - 1 { -1i8 } else { 0 };
                     self.a.x = (self.a.x as i8 + delta_x) as usize;
                     self.a.y += 2;
                     self.b.x = (self.b.x as i8 + 1 + delta_x) as usize;
@@ -137,7 +207,7 @@ impl Block {
                     // DC
                     //  B
                     //  A
-                    let delta_x = if self.c.x == BOARD_WIDTH - 1 { -1i8 } else { 0 };
+                    let delta_x = if self.c.x == width - 1 { -1i8 } else { 0 };
                     self.a.x = (self.a.x as i8 + 1 + delta_x) as usize;
                     self.a.y -= 1;
                     self.b.x = (self.b.x as i8 + delta_x) as usize;
@@ -161,7 +231,7 @@ impl Block {
                     // AD
                     // B
                     // C
-                    let delta_x = if self.d.x == BOARD_WIDTH - 1 { -1i8 } else { 0 };
+                    let delta_x = if self.d.x == width - 1 { -1i8 } else { 0 };
                     self.a.x = (self.a.x as i8 + delta_x) as usize;
                     self.a.y += 2;
                     self.b.x = (self.b.x as i8 + 1 + delta_x) as usize;
@@ -182,7 +252,7 @@ impl Block {
                     //  C
                     //  B
                     // DA
-                    let delta_x = if self.c.x == BOARD_WIDTH - 1 { -1i8 } else { 0 };
+                    let delta_x = if self.c.x == width - 1 { -1i8 } else { 0 };
                     self.a.x = (self.a.x as i8 + 1 + delta_x) as usize;
                     self.a.y -= 1;
                     self.b.x = (self.b.x as i8 + delta_x) as usize;
@@ -206,7 +276,7 @@ impl Block {
                     //  A
                     // DB
                     //  C
-                    let delta_x = if self.a.x == BOARD_WIDTH - 1 { -1i8 } else { 0 };
+                    let delta_x = if self.a.x == width - 1 { -1i8 } else { 0 };
                     self.a.x = (self.a.x as i8 - 1 + delta_x) as usize;
                     self.a.y += 1;
                     self.b.x = (self.b.x as i8 + delta_x) as usize;
@@ -227,7 +297,7 @@ impl Block {
                     // A
                     // BD
                     // C
-                    let delta_x = if self.d.x == BOARD_WIDTH - 1 { -1i8 } else { 0 };
+                    let delta_x = if self.d.x == width - 1 { -1i8 } else { 0 };
                     self.a.x = (self.a.x as i8 + delta_x) as usize;
                     self.a.y += 2;
                     self.b.x = (self.b.x as i8 + 1 + delta_x) as usize;
@@ -307,6 +377,14 @@ const SPEED: [u64; 11] = [
     100 / DRAW_SPEED
 ];
 
+// frames between cementing a block and spawning the next one
+const SPAWN_DELAY: u64 = 250 / DRAW_SPEED;
+
+const TETRIS_PAUSE: Duration = Duration::from_millis(1500);
+
+// frames counted down before a timed game starts
+const COUNTDOWN: u64 = 3000 / DRAW_SPEED;
+
 const BLOCKS: [Block; 7] = [
     Block { a: Coord { x: 4, y: 0 }, b: Coord { x: 4, y: 1 }, c: Coord { x: 4, y: 2 }, d: Coord { x: 4, y: 3 }, color: GREEN },
     Block { a: Coord { x: 4, y: 0 }, b: Coord { x: 4, y: 1 }, c: Coord { x: 5, y: 0 }, d: Coord { x: 5, y: 1 }, color: BLUE },
@@ -319,30 +397,259 @@ const BLOCKS: [Block; 7] = [
 
 const BOARD_HEIGHT: usize = 20;
 const BOARD_WIDTH: usize = 10;
+// rows new blocks appear in
+const SPAWN_ROWS: usize = 4;
+
+/// What the last placement cleared, for goals and attacks that care about more than the line count.
+#[derive(Copy, Clone, Default)]
+struct Clear {
+    lines: u32,
+    // a T turned into a spot with three of its corners filled
+    tspin: bool,
+    // nothing left on the board
+    perfect: bool,
+}
 
+#[derive(Clone)]
 struct Game {
     board: [[u8; BOARD_HEIGHT]; BOARD_WIDTH],
+    // frame every cell was filled in and of the last line clear, for stacks that fade from view
+    placed: [[u64; BOARD_HEIGHT]; BOARD_WIDTH],
+    cleared_frame: u64,
     block: Block,
     next_block: Block,
+    hold_block: Option<Block>,
+    hold_used: bool,
     lines_cleared: u32,
     level: usize,
-    score: u32
+    score: u32,
+    pieces: u32,
+    drop_timer: u64,
+    // frames the block rested on the stack, only modes with a lock delay count them
+    lock_timer: u64,
+    spawn_delay: u64,
+    countdown: u64,
+    // frames played since the countdown ended, the real time they took in milliseconds
+    // and the milliseconds of each, so a replay times them the same
+    clock: u64,
+    millis: u64,
+    timing: Vec<u64>,
+    // time and score at the end of every level
+    splits: Vec<(u64, u32)>,
+    // inputs given during the spawn delay, applied when the next block spawns
+    initial_turns: u8,
+    initial_hold: bool,
+    // every random decision comes from this generator, so a seed replays the same game
+    seed: u64,
+    // the seed was given rather than drawn, a restart deals the same blocks again
+    fixed_seed: bool,
+    rng: Randomizer,
+    // except the holes of garbage from opponents, drawn from a fork of it so both players keep getting the same blocks
+    garbage_rng: Randomizer,
+    rules: Rules,
+    // frames played so far and every move with the frame it was made in
+    frame: u64,
+    inputs: Vec<(u64, Move)>,
+    tetris: bool,
+    // placements were undone, which rules the game out for records
+    assisted: bool,
+    // garbage rows still to come up from below and the column of the last hole, off the board before the first
+    garbage_left: u32,
+    hole: usize,
+    // the last thing the block did was turn, which makes a T placed like that a T-spin
+    last_turned: bool,
+    last_clear: Clear,
+    // fixed blocks to play before random ones and how many were dealt, empty outside puzzles and the sandbox
+    sequence: Vec<Block>,
+    dealt: usize,
+    // turns and moves made with the block in play and placements that needed fewer of them
+    finesse_keys: u32,
+    finesse_faults: u32,
+    // where the block in play should go in a finesse drill
+    target: Option<Block>,
+    // frames since garbage last rose in survival, the hole of the coming row is `hole`
+    rise_timer: u64,
+    // garbage sent by opponents waiting to rise and rows to send that weren't delivered yet
+    incoming: Vec<Batch>,
+    outgoing: u32,
+    // placements in a row that cleared lines, and whether the last clear was a tetris or T-spin
+    combo: u32,
+    back_to_back: bool,
 }
 impl Game {
-    pub fn new() -> Game {
-        Game {
+    pub fn new(seed: u64, rules: Rules) -> Game {
+        let mut rng = Randomizer::new(seed);
+        let mut game = Game {
             board: [[0u8; BOARD_HEIGHT]; BOARD_WIDTH],
-            block: Self::random_block(),
-            next_block: Self::random_block(),
+            placed: [[0; BOARD_HEIGHT]; BOARD_WIDTH],
+            cleared_frame: 0,
+            block: Self::random_block(&mut rng),
+            next_block: Self::random_block(&mut rng),
+            hold_block: None,
+            hold_used: false,
             lines_cleared: 0,
             level: 0,
-            score: 0
+            score: 0,
+            pieces: 0,
+            drop_timer: 0,
+            lock_timer: 0,
+            spawn_delay: 0,
+            countdown: if rules.mode.timed() { COUNTDOWN } else { 0 },
+            clock: 0,
+            millis: 0,
+            timing: Vec::new(),
+            splits: Vec::new(),
+            initial_turns: 0,
+            initial_hold: false,
+            seed,
+            fixed_seed: false,
+            rng,
+            garbage_rng: garbage_randomizer(seed),
+            rules,
+            frame: 0,
+            inputs: Vec::new(),
+            tetris: false,
+            assisted: false,
+            garbage_left: 0,
+            hole: BOARD_WIDTH,
+            last_turned: false,
+            last_clear: Clear::default(),
+            sequence: Vec::new(),
+            dealt: 0,
+            finesse_keys: 0,
+            finesse_faults: 0,
+            target: None,
+            rise_timer: 0,
+            incoming: Vec::new(),
+            outgoing: 0,
+            combo: 0,
+            back_to_back: false,
+        };
+        game.block = game.centered(game.block);
+        game.next_target();
+        if let Mode::Dig { rows, messiness, visible } = rules.mode {
+            game.garbage_left = rows;
+            // an empty board has room for all of it
+            game.fill_garbage(visible, messiness);
+        }
+        if let Mode::Survival { messiness } = rules.mode {
+            game.next_hole(messiness);
+        }
+        game
+    }
+
+    /// Advances the game by one frame, returns false when the game is over.
+    pub fn tick(&mut self) -> bool {
+        self.timed_tick(DRAW_SPEED)
+    }
+
+    /// Advances the game by one frame that took `millis` of real time, which counts once the countdown is over.
+    pub fn timed_tick(&mut self, millis: u64) -> bool {
+        self.frame += 1;
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return true;
+        }
+        self.clock += 1;
+        self.millis += millis;
+        self.timing.push(millis);
+        if self.spawning() {
+            self.spawn_delay -= 1;
+            if !self.spawning() && !self.next_block() {
+                return false;
+            }
+        } else if self.rules.mode == Mode::Master {
+            self.master_frame();
+        } else {
+            self.drop_timer += 1;
+            // past the last speed the levels keep counting but the blocks don't fall any faster
+            if self.drop_timer >= SPEED[self.level.min(SPEED.len() - 1)] {
+                self.drop_timer = 0;
+                if !self.move_block_down() {
+                    self.cement_block();
+                    self.spawn_delay = self.rules.spawn_delay;
+                    if let Mode::Dig { messiness, visible, .. } = self.rules.mode {
+                        if !self.fill_garbage(visible, messiness) {
+                            return false;
+                        }
+                    }
+                    if self.rules.mode == Mode::Versus && !self.trade_garbage() {
+                        return false;
+                    }
+                }
+            }
+        }
+        if let Mode::Survival { messiness } = self.rules.mode {
+            if !self.survival_frame(messiness) {
+                return false;
+            }
         }
+        // reaching the goal ends the game right away, even with a block still falling
+        !self.finished()
     }
 
+    /// Whether the goal of the mode was reached, which ends the game as well.
+    pub fn finished(&self) -> bool {
+        self.rules.mode.goal_reached(self)
+    }
+
+    pub fn time_millis(&self) -> u64 {
+        self.millis
+    }
+
+    pub fn spawning(&self) -> bool {
+        self.spawn_delay > 0
+    }
+
+    /// Plays a move in the current frame, remembering it for the replay.
+    pub fn apply(&mut self, mov: Move) {
+        if !mov.plays_game() || self.countdown > 0 {
+            return;
+        }
+        self.inputs.push((self.frame, mov));
+        if self.spawning() {
+            self.initial_input(&mov);
+            return;
+        }
+        if matches!(mov, Up | Right | Left) {
+            self.finesse_keys += 1;
+        }
+        match mov {
+            Up => self.turn_block(),
+            Down => self.soft_drop(),
+            Right => self.move_block_right(),
+            Left => self.move_block_left(),
+            Hold => self.hold(),
+            _ => false,
+        };
+    }
+
+    /// FNV-1a hash over everything that decides how the game continues, stable between versions
+    /// so replays of the same game can be compared.
+    pub fn state_hash(&self) -> u64 {
+        let blocks = [Some(self.block), Some(self.next_block), self.hold_block];
+        let coords = blocks.iter().flatten()
+            .flat_map(|b| [b.color as u8 as u64, b.a.x as u64, b.a.y as u64, b.b.x as u64, b.b.y as u64,
+                b.c.x as u64, b.c.y as u64, b.d.x as u64, b.d.y as u64]);
+        let numbers = [self.score as u64, self.lines_cleared as u64, self.level as u64, self.pieces as u64,
+            self.frame, self.rng.state()];
+        self.board.iter().flatten().map(|cell| *cell as u64)
+            .chain(coords)
+            .chain(numbers)
+            .flat_map(u64::to_le_bytes)
+            .fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    /// Whether a tetris was scored since the last call, so it can be celebrated.
+    pub fn take_tetris(&mut self) -> bool {
+        mem::take(&mut self.tetris)
+    }
+
+    /// Clears the full lines and returns how many, a big line counts as the pair of rows it covers
+    /// in the lines cleared.
     pub fn clear_lines(&mut self) -> u32 {
         let mut lines_cleared = 0;
-        for y in 0..BOARD_HEIGHT {
+        for y in 0..self.height() {
             if self.line_complete(y) {
                 lines_cleared += 1;
                 self.clear_line(y);
@@ -350,24 +657,38 @@ impl Game {
                 self.clear_line(0);
             }
         }
-        self.lines_cleared += lines_cleared;
-        return lines_cleared
+        self.lines_cleared += lines_cleared * self.scale() as u32;
+        lines_cleared
     }
 
     pub fn add_lines_to_score(&mut self, lines: u32) {
-        match lines {
-            1 => self.score += 100,
-            2 => self.score += 250,
-            3 => self.score += 500,
-            4 => self.score += 800,
+        let points = match lines {
+            1 => 100,
+            2 => 250,
+            3 => 500,
+            4 => 800,
             _ => {
                 panic!("cleared {} lines?", lines);
             }
-        }
+        };
+        self.score += points * self.scale() as u32;
+    }
+
+    /// Rows and columns of the board a cell takes, big blocks play on a board of half the size.
+    pub fn scale(&self) -> usize {
+        if self.rules.big { 2 } else { 1 }
+    }
+
+    pub fn width(&self) -> usize {
+        BOARD_WIDTH / self.scale()
+    }
+
+    pub fn height(&self) -> usize {
+        BOARD_HEIGHT / self.scale()
     }
 
     fn line_complete(&self, line: usize) -> bool {
-        for x in 0..BOARD_WIDTH {
+        for x in 0..self.width() {
             if self.board[x][line] == 0 {
                 return false;
             }
@@ -375,11 +696,25 @@ impl Game {
         true
     }
 
+    /// Removes rows from the bottom until the stack is out of the way of new blocks.
+    fn clear_spawn_area(&mut self) {
+        while (0..SPAWN_ROWS).any(|y| !self.line_empty(y)) {
+            self.clear_line(self.height() - 1);
+            self.move_lines_down(self.height() - 1);
+            self.clear_line(0);
+        }
+    }
+
+    fn line_empty(&self, line: usize) -> bool {
+        (0..BOARD_WIDTH).all(|x| self.board[x][line] == 0)
+    }
+
     fn move_lines_down(&mut self, start_above_line: usize) {
         for mut y2 in 0..start_above_line {
             y2 = start_above_line - 1 - y2;
             for x in 0..BOARD_WIDTH {
                 self.board[x][y2 + 1] = self.board[x][y2];
+                self.placed[x][y2 + 1] = self.placed[x][y2];
             }
         }
     }
@@ -387,17 +722,20 @@ impl Game {
     fn clear_line(&mut self, line: usize) {
         for x in 0..BOARD_WIDTH {
             self.board[x][line] = 0;
+            self.placed[x][line] = 0;
         }
     }
 
     pub fn turn_block(&mut self) -> bool {
-        self.block.turn();
+        let (width, height) = (self.width(), self.height());
+        self.block.turn(width, height);
         if !self.legal_move() {
-            self.block.turn();
-            self.block.turn();
-            self.block.turn();
+            self.block.turn(width, height);
+            self.block.turn(width, height);
+            self.block.turn(width, height);
             return false;
         }
+        self.last_turned = true;
         true
     }
 
@@ -408,9 +746,20 @@ impl Game {
             self.block.move_up();
             return false;
         }
+        self.last_turned = false;
         true
     }
 
+    fn soft_drop(&mut self) -> bool {
+        if self.move_block_down() {
+            return true;
+        }
+        if self.rules.mode == Mode::Master {
+            self.lock_now();
+        }
+        false
+    }
+
     pub fn move_block_right(&mut self) -> bool {
         if !self.block.move_right() {
             return false;
@@ -418,6 +767,7 @@ impl Game {
             self.block.move_left();
             return false;
         }
+        self.last_turned = false;
         true
     }
 
@@ -428,11 +778,78 @@ impl Game {
             self.block.move_right();
             return false;
         }
+        self.last_turned = false;
+        true
+    }
+
+    pub fn hold(&mut self) -> bool {
+        // the last block of a puzzle can't be held away with nothing to come instead
+        if self.hold_used || !self.rules.mode.holds() || self.hold_block.is_none() && !self.next_dealt() {
+            return false;
+        }
+        let (current, next) = (self.block, self.next_block);
+        let dealt = self.dealt;
+        let held = self.hold_block.replace(Self::spawn_form(current));
+        match held {
+            Some(block) => self.block = self.centered(block),
+            None => {
+                self.block = self.centered(next);
+                self.next_block = self.deal();
+            }
+        }
+        if !self.legal_move() {
+            // no room to swap in the held block, undo
+            if held.is_none() {
+                self.next_block = next;
+                self.dealt = dealt;
+            }
+            self.hold_block = held;
+            self.block = current;
+            return false;
+        }
+        self.hold_used = true;
+        self.drop_timer = 0;
+        self.lock_timer = 0;
+        // the block swapped in starts over from where blocks appear
+        self.finesse_keys = 0;
         true
     }
 
-    fn random_block() -> Block {
-        BLOCKS[rand::thread_rng().gen_range(0..7)]
+    fn random_block(rng: &mut Randomizer) -> Block {
+        BLOCKS[rng.gen_range(0..7)]
+    }
+
+    /// Moves a block from where it appears on a full size board to the middle of this one.
+    fn centered(&self, mut block: Block) -> Block {
+        for _ in 0..(BOARD_WIDTH - self.width()) / 2 {
+            block.move_left();
+        }
+        block
+    }
+
+    /// The block to come after the next one, from the sequence when there is one.
+    fn deal(&mut self) -> Block {
+        if self.sequence.is_empty() {
+            return Self::random_block(&mut self.rng);
+        }
+        self.dealt += 1;
+        match self.sequence.get(self.dealt - 1) {
+            Some(block) => *block,
+            // the sequence ran out, the stand-in is never played
+            None => Self::random_block(&mut self.rng),
+        }
+    }
+
+    /// Whether the next block is one to play, false once the blocks of a puzzle ran out,
+    /// random ones follow a sequence anywhere else.
+    fn next_dealt(&self) -> bool {
+        !matches!(self.rules.mode, Mode::Puzzle { .. }) || self.dealt <= self.sequence.len()
+    }
+
+    fn spawn_form(block: Block) -> Block {
+        *BLOCKS.iter()
+            .find(|b| b.color as u8 == block.color as u8)
+            .expect("unknown block color")
     }
 
     fn legal_move(&self) -> bool {
@@ -443,101 +860,311 @@ impl Game {
     }
 
     fn check_free(&self, coord: &Coord) -> bool {
-        self.board[coord.x][coord.y] == 0
+        coord.x < self.width() && coord.y < self.height() && self.board[coord.x][coord.y] == 0
     }
 
     pub fn next_block(&mut self) -> bool {
-        self.block = self.next_block;
-        self.next_block = Self::random_block();
-        if self.board[self.block.a.x][self.block.a.y] != 0
-            || self.board[self.block.b.x][self.block.b.y] != 0
-            || self.board[self.block.c.x][self.block.c.y] != 0
-            || self.board[self.block.d.x][self.block.d.y] != 0 {
-            return false;
+        if self.next_dealt() {
+            self.block = self.centered(self.next_block);
+            self.next_block = self.deal();
+        } else {
+            // out of blocks, a held one is the last to play
+            match self.hold_block.take() {
+                Some(block) => self.block = self.centered(block),
+                None => return false,
+            }
+        }
+        self.next_target();
+        self.hold_used = false;
+        self.drop_timer = 0;
+        self.lock_timer = 0;
+        self.last_turned = false;
+
+        if mem::take(&mut self.initial_hold) {
+            self.hold();
+        }
+
+        let turns = mem::take(&mut self.initial_turns);
+        if turns > 0 {
+            let spawned = self.block;
+            for _ in 0..turns {
+                self.block.turn(self.width(), self.height());
+            }
+            if !self.legal_move() {
+                // rotated spawn is blocked, fall back to the regular orientation
+                self.block = spawned;
+            } else {
+                self.finesse_keys = turns as u32;
+            }
+        }
+        self.legal_move()
+    }
+
+    /// Input while waiting for the next block to spawn, only turns and hold are remembered.
+    pub fn initial_input(&mut self, mov: &Move) {
+        match mov {
+            Up => self.initial_turns = (self.initial_turns + 1) % 4,
+            Hold => self.initial_hold = !self.initial_hold,
+            _ => {}
         }
-        true
     }
 
     pub fn cement_block(&mut self) {
-        self.board[self.block.a.x][self.block.a.y] = self.block.color as u8;
-        self.board[self.block.b.x][self.block.b.y] = self.block.color as u8;
-        self.board[self.block.c.x][self.block.c.y] = self.block.color as u8;
-        self.board[self.block.d.x][self.block.d.y] = self.block.color as u8;
+        let tspin = self.block.color as u8 == WHITE as u8 && self.last_turned && self.filled_corners(self.block.b) >= 3;
+        self.judge_finesse();
+        for coord in [self.block.a, self.block.b, self.block.c, self.block.d] {
+            self.board[coord.x][coord.y] = self.block.color as u8;
+            self.placed[coord.x][coord.y] = self.frame;
+        }
 
         self.score += 10;
+        self.pieces += 1;
 
         let lines = self.clear_lines();
+        if lines > 0 {
+            self.cleared_frame = self.frame;
+        }
+        self.last_clear = Clear {
+            lines,
+            tspin,
+            perfect: lines > 0 && (0..BOARD_HEIGHT).all(|y| self.line_empty(y)),
+        };
+        if !self.rules.mode.tops_out() {
+            self.clear_spawn_area();
+        }
+        if self.rules.mode == Mode::Master {
+            self.advance_master_level(lines);
+        }
         if lines > 0 {
             self.add_lines_to_score(lines);
 
-            if self.lines_cleared >= ((self.level + 1) * 10) as u32 {
+            if self.rules.mode.levels_up() && self.lines_cleared >= ((self.level + 1) * 10) as u32 {
                 // upgrade level
-                self.level = (self.level + 1).min(SPEED.len() - 1);
+                self.splits.push((self.millis, self.score));
+                self.level += 1;
             }
 
             if lines == 4 {
                 // celebrate tetris
-                self.tetris();
+                self.tetris = true;
             }
         }
     }
 
+    /// Corners diagonal to `center` that are filled or off the board.
+    fn filled_corners(&self, center: Coord) -> usize {
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter()
+            .filter(|(dx, dy)| {
+                let (x, y) = (center.x as i32 + dx, center.y as i32 + dy);
+                x < 0 || y < 0 || x >= self.width() as i32 || y >= self.height() as i32
+                    || self.board[x as usize][y as usize] != 0
+            })
+            .count()
+    }
+
     pub fn draw(&self) {
-        restore_cursor();
-        overwrite();
-        println!("{}", color!("   ┏━━━━━━━━━━┓", WHITE));
+        self.draw_screen(false, false, &[]);
+    }
+
+    /// Draws the pause menu instead of the board, hiding anything that could help planning ahead.
+    pub fn draw_paused(&self) {
+        self.draw_screen(true, false, &[]);
+    }
+
+    fn draw_screen(&self, paused: bool, reveal: bool, messages: &[(usize, String)]) {
+        self.render(&mut io::stdout().lock(), paused, reveal, messages).expect("Cannot write to stdout");
+    }
+
+    /// Draws the screen to any output, over the one drawn there before.
+    pub fn render(&self, out: &mut impl Write, paused: bool, reveal: bool, messages: &[(usize, String)]) -> io::Result<()> {
+        restore_cursor_to(out)?;
+        for line in self.screen(paused, reveal, messages) {
+            overwrite_to(out)?;
+            write!(out, "{}\r\n", line)?;
+        }
+        Ok(())
+    }
+
+    /// Lines of the board with everything around it, with `reveal` showing a stack that faded from view
+    /// and `messages` shown instead of the board rows they are given with.
+    pub fn screen(&self, paused: bool, reveal: bool, messages: &[(usize, String)]) -> Vec<String> {
+        let mut lines = vec![color!("       ┏━━━━━━━━━━┓", WHITE)];
         for y in 0..BOARD_HEIGHT {
-            overwrite();
-            print!("{}", color!("   ┃", WHITE));
-            for x in 0..BOARD_WIDTH {
-                if Self::match_block(self.block, x, y) {
-                    Self::draw_block(self.block.color as u8);
-                } else {
-                    if self.board[x][y] == 0 {
-                        print!(" ");
-                    } else {
-                        Self::draw_block(self.board[x][y]);
+            let mut line = match y {
+                1 => format!(" {}  ", color!("Hold", BLUE)),
+                3..=6 if !paused => self.hold_line(y - 3),
+                9 if self.rules.practice => format!(" {}", color!("z undo", BLUE)),
+                10 if self.rules.practice => format!(" {}", color!("x redo", BLUE)),
+                12 if self.rules.mode == Mode::Sandbox => format!(" {}", color!("e edit", BLUE)),
+                15 if self.shows_finesse() => format!(" {}", color!("Faults", BLUE)),
+                16 if self.shows_finesse() => format!("{0:6} ", self.finesse_faults),
+                _ => "       ".to_string(),
+            };
+            // the border fills up from the bottom with the garbage waiting to rise, red where it's ready to
+            let (ready, waiting) = (self.ready_rows() as usize * self.scale(), self.incoming_rows() as usize * self.scale());
+            line += &match BOARD_HEIGHT - y {
+                rows if rows <= ready => color!("┃", RED),
+                rows if rows <= waiting => color!("┃", YELLOW),
+                _ => color!("┃", WHITE),
+            };
+            if let Some((_, message)) = messages.iter().find(|(row, _)| *row == y) {
+                line += message;
+            } else if paused {
+                line += &Self::pause_line(y);
+            } else if y == 9 && self.countdown > 0 {
+                let seconds = (self.countdown * DRAW_SPEED).div_ceil(1000);
+                line += &color!(format!("{:^1$}", seconds, BOARD_WIDTH), YELLOW);
+            } else {
+                line += &self.board_line(y, reveal);
+            }
+            line += &color!("┃ ", WHITE);
+            line += &match y {
+                 0 => match self.rules.mode {
+                     Mode::Zen => color!("Zen", GREEN),
+                     Mode::Puzzle { goal, .. } => color!(goal.describe(), GREEN),
+                     mode if mode.timed() => color!("Time", BLUE),
+                     _ => String::new(),
+                 },
+                 1 if self.rules.mode.timed() => format_time(self.rules.mode.shown_time(self)),
+                 2 => match self.rules.mode {
+                     Mode::Dig { .. } => color!("Garbage", BLUE),
+                     Mode::Puzzle { .. } => color!("Pieces", BLUE),
+                     Mode::Finesse { .. } => color!("Targets", BLUE),
+                     Mode::Survival { .. } => color!("Rises in", BLUE),
+                     _ => String::new(),
+                 },
+                 3 => match self.rules.mode {
+                     Mode::Dig { .. } => format!("{0:5}", self.garbage_left + self.garbage_rows()),
+                     Mode::Puzzle { .. } => format!("{0:5}", self.sequence.len() as u32 - self.pieces),
+                     Mode::Finesse { targets } => format!("{0:5}/{1}", self.pieces, targets),
+                     Mode::Survival { .. } => format!("{0:5.1}s", (self.rise_left() * DRAW_SPEED) as f64 / 1000.0),
+                     _ => String::new(),
+                 },
+                 4 => color!("Next", BLUE),
+                 5 => color!("Block", BLUE),
+                 7..=10 if !paused => self.next_line(y - 7),
+                12 => color!("Level", BLUE),
+                13 => match self.rules.mode {
+                    // the level to reach next is where the level stops until a line is cleared
+                    Mode::Master => format!("{0:5}/{1}", self.level, (self.level / 100 + 1) * 100 - 1),
+                    _ => format!("{0:5}", self.level + 1),
+                },
+                15 => color!("Lines", BLUE),
+                16 => match self.rules.mode {
+                    Mode::Sprint { lines } | Mode::Marathon { goal: Some(lines) } => {
+                        format!("{0:5}/{1}", self.lines_cleared, lines)
                     }
+                    _ => format!("{0:5}", self.lines_cleared),
+                },
+                18 => color!("Score", BLUE),
+                19 => format!("{0:5}", self.score),
+                _ => String::new(),
+            };
+            lines.push(line);
+        }
+        lines.push(color!("       ┗━━━━━━━━━━┛", WHITE));
+        if let Mode::Survival { .. } = self.rules.mode {
+            lines.push(self.coming_garbage_line());
+        }
+        lines
+    }
+
+    /// The row of garbage to rise next, under the board.
+    fn coming_garbage_line(&self) -> String {
+        let mut line = "        ".to_string();
+        for column in 0..BOARD_WIDTH {
+            line += &match (column / self.scale() == self.hole, self.rise_warning()) {
+                (true, _) => " ".to_string(),
+                (false, true) => blink!("▒", BLACK),
+                (false, false) => Self::cell(GARBAGE),
+            };
+        }
+        line
+    }
+
+    fn board_line(&self, row: usize, reveal: bool) -> String {
+        let mut line = String::new();
+        for column in 0..BOARD_WIDTH {
+            let (x, y) = (column / self.scale(), row / self.scale());
+            line += &if !self.spawning() && self.countdown == 0 && Self::match_block(self.block, x, y) {
+                Self::cell(self.block.color as u8)
+            } else if self.board[x][y] == 0 {
+                match self.target {
+                    Some(target) if Self::match_block(target, x, y) => color!("░", target.color),
+                    _ => " ".to_string(),
                 }
-            }
-            print!("{}", color!("┃ ", WHITE));
-            match y {
-                 4 => print!("{}", color!("Next", BLUE)),
-                 5 => print!("{}", color!("Block", BLUE)),
-                 7 => self.draw_block_line(0),
-                 8 => self.draw_block_line(1),
-                 9 => self.draw_block_line(2),
-                10 => self.draw_block_line(3),
-                12 => print!("{}", color!("Level", BLUE)),
-                13 => print!("{0:5}", self.level + 1),
-                14 => {}
-                15 => print!("{}", color!("Lines", BLUE)),
-                16 => print!("{0:5}", self.lines_cleared),
-                17 => {}
-                18 => print!("{}", color!("Score", BLUE)),
-                19 => print!("{0:5}", self.score),
-                _ => {}
-            }
-            println!();
+            } else if reveal || !self.faded(x, y) {
+                Self::cell(self.board[x][y])
+            } else if self.frame - self.cleared_frame < self.rules.outline && self.on_surface(x, y) {
+                color!("░", WHITE)
+            } else {
+                " ".to_string()
+            };
+        }
+        line
+    }
+
+    fn shows_finesse(&self) -> bool {
+        self.rules.finesse || matches!(self.rules.mode, Mode::Finesse { .. })
+    }
+
+    /// Whether a cell of the stack faded from view, the game plays on the same either way.
+    fn faded(&self, x: usize, y: usize) -> bool {
+        self.rules.invisible && self.frame.saturating_sub(self.placed[x][y]) >= self.rules.fade
+    }
+
+    /// Cells of the stack next to an empty one, which outline its shape.
+    fn on_surface(&self, x: usize, y: usize) -> bool {
+        [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)].iter()
+            .any(|&(x, y)| x < self.width() && y < self.height() && self.board[x][y] == 0)
+    }
+
+    fn pause_line(y: usize) -> String {
+        match y {
+            8 => blink!("  PAUSED  ", YELLOW),
+            10 => color!(" p resume ", WHITE),
+            11 => color!(" r restart", WHITE),
+            12 => color!(" q quit   ", WHITE),
+            _ => format!("{:1$}", "", BOARD_WIDTH),
         }
-        overwrite();
-        println!("{}", color!("   ┗━━━━━━━━━━┛", WHITE));
     }
 
-    fn draw_block_line(&self, y: usize) {
+    fn hold_line(&self, y: usize) -> String {
+        let mut line = "  ".to_string();
+        for x in 4..6 {
+            line += &match self.hold_block {
+                Some(block) if Self::match_block(block, x, y) => {
+                    // a held block can't be swapped back until the next one spawns
+                    Self::cell(if self.hold_used { BLACK as u8 } else { block.color as u8 })
+                }
+                _ => " ".to_string(),
+            };
+        }
+        line + "   "
+    }
+
+    fn next_line(&self, y: usize) -> String {
+        if !self.next_dealt() {
+            return String::new();
+        }
+        let mut line = String::new();
         if Self::match_block(self.next_block, 4, y) {
-            print!(" ");
-            Self::draw_block(self.next_block.color as u8);
+            line += " ";
+            line += &Self::cell(self.next_block.color as u8);
         } else {
-            print!("  ");
+            line += "  ";
         }
         if Self::match_block(self.next_block, 5, y) {
-            Self::draw_block(self.next_block.color as u8);
+            line += &Self::cell(self.next_block.color as u8);
         }
+        line
     }
 
-    fn draw_block(color: u8) {
-        print!("{}", inverted!("╳", color));
+    fn cell(color: u8) -> String {
+        if color == GARBAGE {
+            return color!("▒", BLACK);
+        }
+        inverted!("╳", color)
     }
 
     fn match_block(block: Block, x: usize, y: usize) -> bool {
@@ -550,13 +1177,13 @@ impl Game {
         false
     }
 
-    pub fn tetris(&self) {
+    pub fn tetris(&self, pause: Duration) {
         self.draw();
         restore_cursor();
         for _ in 0..10 {
             println!();
         }
-        print!("{}", color!("   ┃", WHITE));
+        print!("{}", color!("       ┃", WHITE));
         println!("{}{}{}{}{}{}{}{}{}{}",
                  color!("~", WHITE),
                  color!("=", GREEN),
@@ -568,131 +1195,316 @@ impl Game {
                  color!("S", YELLOW),
                  color!("=", GREEN),
                  color!("~", WHITE));
-        sleep(Duration::from_millis(1500));
+        sleep(pause);
     }
 
     pub fn game_over(&self) {
-        self.draw();
-        restore_cursor();
-        for y in 0..BOARD_HEIGHT + 2 {
-            if y == 11 {
-                print!("{}", color!("   ┃", WHITE));
-                println!("{}", blink!("GAME OVER!", RED));
-            } else {
-                println!()
-            }
-        }
+        self.draw_with_messages(&[(10, blink!("GAME OVER!", RED))]);
+        line_in_color(&format!("Seed {}", self.seed), BLUE);
+    }
+
+    /// Draws the game with messages over the board, each given with the board row to put it on.
+    pub fn draw_with_messages(&self, messages: &[(usize, String)]) {
+        // the game is over, a stack that faded from view is shown again
+        self.draw_screen(false, true, messages);
     }
 }
 
 fn main() {
-    do_in_game_terminal(play_game);
+    let options = Options::from_args().unwrap_or_else(|error| {
+        eprintln!("{}\n\n{}", error, USAGE);
+        process::exit(2);
+    });
+    if let Some(path) = &options.verify {
+        process::exit(verify(path));
+    }
+    if let Some(path) = &options.replay {
+        let replay = load_replay(path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+        do_in_game_terminal(|| play_replay(replay));
+        return;
+    }
+    if let Some(path) = &options.puzzles {
+        let pack = load_pack(path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+        do_in_game_terminal(|| play_puzzles(pack, options.practice));
+        return;
+    }
+    if let Some(port) = options.royale {
+        let royale = Royale::open(port, options.holes).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+        do_in_game_terminal(|| royale.run());
+        return;
+    }
+    if let Some(port) = options.serve {
+        if let Err(error) = serve(port, options.rules()) {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+        return;
+    }
+    if let Some(address) = &options.watch {
+        let updates = tune_in(address).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+        do_in_game_terminal(|| watch(address, updates));
+        return;
+    }
+    if let Some(port) = options.host {
+        let seed = options.seed.unwrap_or_else(random_seed);
+        let session = listen(port).and_then(|listener| host(listener, seed, options.versus.unwrap_or(VERSUS_ROUNDS), options.holes, options.delay()));
+        play_over_network(session);
+        return;
+    }
+    if let Some(address) = &options.connect {
+        play_over_network(join(address, options.delay()));
+        return;
+    }
+    if let Some(rounds) = options.versus {
+        do_in_game_terminal(|| play_versus(rounds, options.seed, options.rules()));
+        return;
+    }
+    if options.mode == Mode::Sandbox {
+        do_in_game_terminal(|| play_sandbox(options.seed.unwrap_or_else(random_seed), options.rules()));
+        return;
+    }
+    let saved_game = match &options.load {
+        Some(path) => Some(load_game(path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        })),
+        // a seed or rules on the command line ask for a new game rather than the saved one
+        None if options.defines_game() => None,
+        None => resume_saved_game(),
+    };
+    let broadcast = options.broadcast.map(|port| Broadcast::start(port).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    }));
+    do_in_game_terminal(|| play_game(saved_game, options.seed, options.rules(), broadcast));
+}
+
+fn play_over_network(session: Result<Session, String>) {
+    let session = session.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+    do_in_game_terminal(|| play_online(session));
 }
 
-fn play_game() {
-    line_in_color("Welcome to Terminal Tetris!\n", YELLOW);
+/// Picks up the game saved when quitting last time, a save is only resumed once.
+fn resume_saved_game() -> Option<Game> {
+    let path = default_save_path();
+    if !path.exists() {
+        return None;
+    }
+    let game = load_game(&path);
+    fs::remove_file(&path).ok();
+    match game {
+        Ok(game) => Some(game),
+        Err(error) => {
+            eprintln!("Ignoring saved game, {}", error);
+            None
+        }
+    }
+}
+
+/// Plays until game over or quit, a restart keeps the chosen seed so the same blocks come again.
+fn play_game(saved_game: Option<Game>, seed: Option<u64>, rules: Rules, mut broadcast: Option<Broadcast>) {
+    // restarting a resumed game plays its rules again, and its seed when that was given
+    let rules = saved_game.as_ref().map_or(rules, |game| game.rules);
+    let seed = saved_game.as_ref().map_or(seed, |game| game.fixed_seed.then_some(game.seed));
+    let new_game = || {
+        let mut game = Game::new(seed.unwrap_or_else(random_seed), rules);
+        game.fixed_seed = seed.is_some();
+        game
+    };
+    match &broadcast {
+        Some(_) => line_in_color("Welcome to Terminal Tetris! The game is broadcast.\n", YELLOW),
+        None => line_in_color("Welcome to Terminal Tetris!\n", YELLOW),
+    }
     create_game_screen(24);
 
-    let mut game = Game::new();
+    // a resumed game starts paused to give the player a moment to get ready
+    let paused = saved_game.is_some();
+    let mut game = saved_game.unwrap_or_else(new_game);
 
-    // input loop
-    let (input_tx, input_rx) = channel();
-    thread::spawn(move || {
-        loop {
-            let mov = move_by_player();
-            input_tx.send(mov).unwrap();
+    let input_rx = input_channel(Some);
+    let ctrl_c_rx = ctrl_c_channel();
+    let game_over = run_game(&mut game, paused, &input_rx, &ctrl_c_rx, new_game, broadcast.as_mut());
+
+    if game_over {
+        if let Some(broadcast) = &mut broadcast {
+            broadcast.game_over(&game);
         }
-    });
+        if game.finished() || game.rules.mode.ranked_at_game_over() {
+            show_results(&game);
+        } else {
+            game.game_over();
+        }
+        match game.rules.mode {
+            Mode::Marathon { .. } => show_splits(&game),
+            Mode::Master => {
+                show_splits(&game);
+                line_in_color(&format!("Grade {}", grade(&game)), YELLOW);
+            }
+            _ => {}
+        }
+        keep_replay(&game);
+    } else if game.rules.mode.saved() {
+        match save_game(&game, &default_save_path()) {
+            Ok(()) => line_in_color("Game saved, it resumes on the next launch.", YELLOW),
+            Err(error) => line_in_color(&format!("Could not save game, {}", error), RED),
+        }
+    }
+}
 
-    // ctrl-c trap
-    let (ctrl_c_tx, ctrl_c_rx) = channel();
-    ctrlc::set_handler(move || ctrl_c_tx.send(())
-        .expect("Could not send signal on channel."))
-        .expect("Error setting Ctrl-C handler");
+/// Plays frames until the game is over, returns false when the player quit before that.
+fn run_game(game: &mut Game, mut paused: bool, input_rx: &Receiver<Key>, ctrl_c_rx: &Receiver<()>,
+            new_game: impl Fn() -> Game + Copy, mut broadcast: Option<&mut Broadcast>) -> bool {
+    let mut practice = game.rules.practice.then(|| Practice::new(game));
 
-    // game loop
+    let mut pacer = Pacer::start();
+    // the timer counts the real time played
+    let mut stopwatch = Stopwatch::start();
     let mut game_over = false;
-    for i in 0.. {
-        let next_tick = i % SPEED[game.level] == 0;
-        if next_tick {
-            if !game.move_block_down() {
-                game.cement_block();
-
-                if !game.next_block() {
-                    game_over = true;
-                    break;
-                }
+    loop {
+        if paused {
+            game.draw_paused();
+            stopwatch.skip();
+        } else {
+            if !game.timed_tick(stopwatch.lap()) {
+                game_over = true;
+                break;
+            }
+            if game.take_tetris() {
+                game.tetris(TETRIS_PAUSE);
+                stopwatch.skip();
+            }
+            if let Some(practice) = &mut practice {
+                practice.record(game);
             }
+            game.draw();
         }
 
-        game.draw();
-
-        handle_input(&mut game, &input_rx);
+        if !handle_input(game, &mut paused, &mut practice, input_rx, new_game) {
+            break;
+        }
+        if let Some(broadcast) = broadcast.as_deref_mut() {
+            broadcast.update(game, paused);
+        }
 
-        if check_ctrl_c(&ctrl_c_rx) {
+        if check_ctrl_c(ctrl_c_rx) {
             // just stop game
             break;
         }
 
-        sleep(Duration::from_millis(DRAW_SPEED));
-    }
-
-    if game_over {
-        game.game_over();
+        pacer.wait();
     }
+    game_over
 }
 
-fn check_ctrl_c(rx: &Receiver<()>) -> bool {
-    return match rx.try_recv() {
-        Ok(_) => {
-            true
-        }
-        Err(_) => {
-            false
-        }
+fn keep_replay(game: &Game) {
+    if !game.rules.mode.replayed() {
+        return;
+    }
+    match save_replay(game) {
+        Ok(path) => line_in_color(&format!("Replay saved to {}", path.display()), BLUE),
+        Err(error) => line_in_color(&format!("Could not save replay, {}", error), RED),
     }
 }
 
-fn handle_input(game: &mut Game, rx: &Receiver<Move>) {
-    loop {
-        match rx.try_recv() {
-            Ok(mov) => {
-                match mov {
-                    Up => game.turn_block(),
-                    Down => game.move_block_down(),
-                    Right => game.move_block_right(),
-                    Left => game.move_block_left()
-                };
-            }
-            Err(_) => {
-                break;
+/// Reads keys on a separate thread, sending on the ones `map` knows about.
+fn input_channel<T: Send + 'static>(map: fn(Key) -> Option<T>) -> Receiver<T> {
+    let (input_tx, input_rx) = channel();
+    thread::spawn(move || {
+        let mut bytes = io::stdin().lock().bytes().map_while(Result::ok);
+        loop {
+            overwrite();
+            io::stdout().flush().expect("Cannot flush stdout");
+            match read_key(&mut bytes) {
+                Some(key) => if let Some(input) = map(key) {
+                    if input_tx.send(input).is_err() {
+                        break;
+                    }
+                },
+                None => break,
             }
         }
-    }
+    });
+    input_rx
 }
 
-fn move_by_player() -> Move {
-    overwrite();
-    io::stdout().flush().expect("Cannot flush stdout");
-    loop {
-        for byte in io::stdin().bytes() {
-            let input_key = byte.unwrap();
+fn ctrl_c_channel() -> Receiver<()> {
+    let (ctrl_c_tx, ctrl_c_rx) = channel();
+    ctrlc::set_handler(move || ctrl_c_tx.send(())
+        .expect("Could not send signal on channel."))
+        .expect("Error setting Ctrl-C handler");
+    ctrl_c_rx
+}
 
-            match input_key {
-                b'w' | b'A' => {
-                    return Up;
-                }
-                b's' | b'B' => {
-                    return Down;
-                }
-                b'd' | b'C' => {
-                    return Right;
-                }
-                b'a' | b'D' => {
-                    return Left;
+fn check_ctrl_c(rx: &Receiver<()>) -> bool {
+    rx.try_recv().is_ok()
+}
+
+/// Applies all pending input, returns false when the player chose to quit.
+fn handle_input(game: &mut Game, paused: &mut bool, practice: &mut Option<Practice>, rx: &Receiver<Key>,
+                new_game: impl Fn() -> Game) -> bool {
+    while let Ok(key) = rx.try_recv() {
+        let Some(mov) = move_for_key(key) else {
+            continue;
+        };
+        if *paused {
+            match mov {
+                Pause => *paused = false,
+                Restart => {
+                    keep_replay(game);
+                    *game = new_game();
+                    *practice = game.rules.practice.then(|| Practice::new(game));
+                    *paused = false;
                 }
+                Quit => return false,
                 _ => {}
             }
+            continue;
+        }
+        match (mov, practice.as_mut()) {
+            (Pause | FocusLost, _) => *paused = true,
+            // the sandbox goes back to editing the position it was played from
+            (Edit, _) if game.rules.mode == Mode::Sandbox => return false,
+            (Undo, Some(practice)) => {
+                practice.undo(game);
+            }
+            (Redo, Some(practice)) => {
+                practice.redo(game);
+            }
+            _ => game.apply(mov),
         }
     }
-}
\ No newline at end of file
+    true
+}
+
+fn move_for_key(key: Key) -> Option<Move> {
+    match key {
+        Key::Char(b'w') | Key::Up => Some(Up),
+        Key::Char(b's') | Key::Down => Some(Down),
+        Key::Char(b'd') | Key::Right => Some(Right),
+        Key::Char(b'a') | Key::Left => Some(Left),
+        Key::Char(b'c') => Some(Hold),
+        Key::Char(b'z') => Some(Undo),
+        Key::Char(b'x') => Some(Redo),
+        Key::Char(b'p') => Some(Pause),
+        Key::Char(b'r') => Some(Restart),
+        Key::Char(b'q') => Some(Quit),
+        Key::Char(b'e') => Some(Edit),
+        Key::FocusOut => Some(FocusLost),
+        _ => None,
+    }
+}
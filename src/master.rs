@@ -0,0 +1,136 @@
+use crate::Game;
+
+// the level that ends the game, every hundred below it holds until a line is cleared
+pub const MASTER_LEVELS: usize = 999;
+// gravity is counted in 1/256 rows per frame, 20 rows a frame drops a block to the floor at once
+const ROW: u64 = 256;
+const TWENTY_G: u64 = 20 * ROW;
+// the fastest a grand master may reach the last level
+const GRAND_MASTER_MILLIS: u64 = (13 * 60 + 30) * 1000;
+
+/// How fast blocks fall and how long the game waits, in frames, from a level on.
+pub struct Speed {
+    level: usize,
+    pub gravity: u64,
+    // frames between placing a block and the next one appearing
+    pub are: u64,
+    // frames a block may rest on the stack before it locks
+    pub lock: u64,
+    // frames added to the wait after a line clear
+    pub clear: u64,
+}
+
+const fn speed(level: usize, gravity: u64, are: u64, lock: u64, clear: u64) -> Speed {
+    Speed { level, gravity, are, lock, clear }
+}
+
+const SPEEDS: [Speed; 34] = [
+    speed(0, 12, 8, 10, 13),
+    speed(30, 18, 8, 10, 13),
+    speed(35, 24, 8, 10, 13),
+    speed(40, 30, 8, 10, 13),
+    speed(50, 36, 8, 10, 13),
+    speed(60, 48, 8, 10, 13),
+    speed(70, 96, 8, 10, 13),
+    speed(80, 144, 8, 10, 13),
+    speed(90, 192, 8, 10, 13),
+    speed(100, 240, 8, 10, 13),
+    speed(120, 288, 8, 10, 13),
+    speed(140, 336, 8, 10, 13),
+    speed(160, 384, 8, 10, 13),
+    speed(170, 432, 8, 10, 13),
+    // a breather before the climb to 20G
+    speed(200, 12, 8, 10, 13),
+    speed(220, 96, 8, 10, 13),
+    speed(230, 192, 8, 10, 13),
+    speed(233, 288, 8, 10, 13),
+    speed(236, 384, 8, 10, 13),
+    speed(239, 480, 8, 10, 13),
+    speed(243, 576, 8, 10, 13),
+    speed(247, 672, 8, 10, 13),
+    speed(251, 768, 8, 10, 13),
+    speed(300, 1536, 8, 10, 13),
+    speed(330, 2304, 8, 10, 13),
+    speed(360, 3072, 8, 10, 13),
+    speed(400, 3840, 8, 10, 13),
+    speed(420, 3072, 8, 10, 13),
+    speed(450, 2304, 8, 10, 13),
+    speed(500, TWENTY_G, 8, 10, 8),
+    speed(600, TWENTY_G, 8, 10, 5),
+    speed(700, TWENTY_G, 5, 10, 4),
+    speed(800, TWENTY_G, 4, 10, 2),
+    speed(900, TWENTY_G, 4, 6, 2),
+];
+
+pub fn speed_at(level: usize) -> &'static Speed {
+    SPEEDS.iter().rev().find(|speed| speed.level <= level).expect("the speeds start at level 0")
+}
+
+// the score each grade takes, the best grade also needs the last level in time to become GM
+const GRADES: [(u32, &str); 10] = [
+    (0, "9"),
+    (1_000, "8"),
+    (2_500, "7"),
+    (5_000, "6"),
+    (8_000, "5"),
+    (12_000, "4"),
+    (17_000, "3"),
+    (24_000, "2"),
+    (32_000, "1"),
+    (45_000, "S"),
+];
+
+pub fn grade(game: &Game) -> &'static str {
+    let (_, grade) = GRADES.iter().rev().find(|(score, _)| game.score >= *score).expect("grades start at 0");
+    if *grade == "S" && game.level >= MASTER_LEVELS && game.time_millis() <= GRAND_MASTER_MILLIS {
+        return "GM";
+    }
+    grade
+}
+
+impl Game {
+    /// Lets gravity pull the block down and locks it once it rested for the lock delay.
+    pub fn master_frame(&mut self) {
+        let speed = speed_at(self.level);
+        self.drop_timer += speed.gravity;
+        while self.drop_timer >= ROW {
+            if self.move_block_down() {
+                self.drop_timer -= ROW;
+                self.lock_timer = 0;
+            } else {
+                self.drop_timer = 0;
+            }
+        }
+        if !self.grounded() {
+            return;
+        }
+        self.lock_timer += 1;
+        if self.lock_timer >= speed.lock {
+            self.cement_block();
+            let speed = speed_at(self.level);
+            self.spawn_delay = speed.are + if self.last_clear.lines > 0 { speed.clear } else { 0 };
+        }
+    }
+
+    /// Pressing down on the stack locks the block without waiting out the lock delay.
+    pub fn lock_now(&mut self) {
+        self.lock_timer = speed_at(self.level).lock;
+    }
+
+    fn grounded(&self) -> bool {
+        let mut below = self.block;
+        !below.move_down() || ![below.a, below.b, below.c, below.d].iter().all(|coord| self.check_free(coord))
+    }
+
+    /// A placed block counts a level unless the level waits at a stop, cleared lines always count.
+    pub fn advance_master_level(&mut self, lines: u32) {
+        let section = self.level / 100;
+        if self.level % 100 != 99 && self.level + 1 < MASTER_LEVELS {
+            self.level += 1;
+        }
+        self.level = (self.level + lines as usize).min(MASTER_LEVELS);
+        if self.level / 100 > section {
+            self.splits.push((self.millis, self.score));
+        }
+    }
+}
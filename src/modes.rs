@@ -0,0 +1,292 @@
+use crate::finesse::FINESSE_TARGETS;
+use crate::master::MASTER_LEVELS;
+use crate::survival::SURVIVAL_MESSINESS;
+use crate::puzzle::Goal;
+use crate::records::Records;
+use crate::terminal::*;
+use crate::terminal::Color::*;
+use crate::{Game, BOARD_HEIGHT, SPAWN_ROWS};
+
+pub const SPRINT_LINES: u32 = 40;
+pub const ULTRA_SECONDS: u32 = 120;
+pub const DIG_ROWS: u32 = 10;
+pub const DIG_MESSINESS: u32 = 100;
+pub const DIG_VISIBLE: u32 = 10;
+
+/// What the player is after, decides when a game ends and which records it counts for.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Mode {
+    // clear the lines while the levels speed up, or play until topping out without a goal
+    Marathon { goal: Option<u32> },
+    // clear the lines as fast as possible
+    Sprint { lines: u32 },
+    // score as much as possible before the time runs out
+    Ultra { seconds: u32 },
+    // relax without topping out, the score lasts for the session only
+    Zen,
+    // clear rows of garbage with holes in them, `visible` rows at a time, as fast as possible
+    Dig { rows: u32, messiness: u32, visible: u32 },
+    // reach the goal with the blocks a puzzle gives, the board and blocks come with the puzzle
+    Puzzle { goal: Goal, hold: bool },
+    // climb to level 999 up to 20G, graded by score and time
+    Master,
+    // play from a position set up in the editor, as often as needed
+    Sandbox,
+    // place blocks where the targets show with as few turns and moves as possible, ranked by the faults
+    Finesse { targets: u32 },
+    // last as long as possible while garbage rises ever faster, scored by the seconds and lines
+    Survival { messiness: u32 },
+    // two players on one keyboard sending each other garbage, the last one standing wins the round
+    Versus,
+}
+
+/// Mode settings from the command line, each mode picks the ones it needs.
+#[derive(Default)]
+pub struct Settings {
+    pub goal: Option<u32>,
+    pub time: Option<u32>,
+    // percent chance the hole moves in each garbage row
+    pub messiness: Option<u32>,
+    pub visible: Option<u32>,
+}
+
+impl Mode {
+    pub fn parse(name: &str, settings: &Settings) -> Result<Mode, String> {
+        match name {
+            "marathon" => Ok(Mode::Marathon { goal: settings.goal }),
+            "sprint" => Ok(Mode::Sprint { lines: settings.goal.unwrap_or(SPRINT_LINES) }),
+            "ultra" => Ok(Mode::Ultra { seconds: settings.time.unwrap_or(ULTRA_SECONDS) }),
+            "zen" => Ok(Mode::Zen),
+            "master" => Ok(Mode::Master),
+            "sandbox" => Ok(Mode::Sandbox),
+            "finesse" => Ok(Mode::Finesse { targets: settings.goal.unwrap_or(FINESSE_TARGETS) }),
+            "survival" => {
+                let messiness = settings.messiness.unwrap_or(SURVIVAL_MESSINESS);
+                if messiness > 100 {
+                    return Err(format!("messiness {} is over 100 percent", messiness));
+                }
+                Ok(Mode::Survival { messiness })
+            }
+            "dig" => {
+                let messiness = settings.messiness.unwrap_or(DIG_MESSINESS);
+                let visible = settings.visible.unwrap_or(DIG_VISIBLE);
+                if messiness > 100 {
+                    return Err(format!("messiness {} is over 100 percent", messiness));
+                }
+                if visible == 0 || visible as usize > BOARD_HEIGHT - SPAWN_ROWS {
+                    return Err(format!("visible garbage must be between 1 and {} rows", BOARD_HEIGHT - SPAWN_ROWS));
+                }
+                Ok(Mode::Dig { rows: settings.goal.unwrap_or(DIG_ROWS), messiness, visible })
+            }
+            _ => Err(format!("unknown mode {}", name)),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        match self {
+            Mode::Marathon { goal: None } => "marathon".to_string(),
+            Mode::Marathon { goal: Some(lines) } => format!("marathon {}", lines),
+            Mode::Sprint { lines } => format!("sprint {}", lines),
+            Mode::Ultra { seconds } => format!("ultra {}", seconds),
+            Mode::Zen => "zen".to_string(),
+            Mode::Dig { rows, messiness, visible } => format!("dig {} {} {}", rows, messiness, visible),
+            Mode::Puzzle { .. } => "puzzle".to_string(),
+            Mode::Master => "master".to_string(),
+            Mode::Sandbox => "sandbox".to_string(),
+            Mode::Finesse { targets } => format!("finesse {}", targets),
+            Mode::Survival { messiness } => format!("survival {}", messiness),
+            Mode::Versus => "versus".to_string(),
+        }
+    }
+
+    pub fn decode(text: &str) -> Result<Mode, String> {
+        let mut words = text.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        if name == "puzzle" {
+            return Err("puzzles can't be resumed or replayed".to_string());
+        }
+        if name == "sandbox" {
+            return Err("sandbox games can't be resumed or replayed".to_string());
+        }
+        if name == "versus" {
+            return Err("versus games can't be resumed or replayed".to_string());
+        }
+        let numbers = words.map(|number| number.parse().map_err(|_| format!("invalid mode: {}", text)))
+            .collect::<Result<Vec<u32>, String>>()?;
+        let number = |index: usize| numbers.get(index).copied();
+        let settings = match name {
+            "ultra" => Settings { time: number(0), ..Settings::default() },
+            "dig" => Settings { goal: number(0), messiness: number(1), visible: number(2), ..Settings::default() },
+            "survival" => Settings { messiness: number(0), ..Settings::default() },
+            _ => Settings { goal: number(0), ..Settings::default() },
+        };
+        Self::parse(name, &settings)
+    }
+
+    /// Timed modes start with a countdown so the clock is fair.
+    pub fn timed(&self) -> bool {
+        matches!(self, Mode::Sprint { .. } | Mode::Ultra { .. } | Mode::Dig { .. } | Mode::Master | Mode::Finesse { .. }
+            | Mode::Survival { .. } | Mode::Versus)
+    }
+
+    /// Whether reaching the top ends the game, zen makes room at the bottom instead.
+    pub fn tops_out(&self) -> bool {
+        !matches!(self, Mode::Zen)
+    }
+
+    /// Survival only ends by topping out, which is when it's ranked.
+    pub fn ranked_at_game_over(&self) -> bool {
+        matches!(self, Mode::Survival { .. })
+    }
+
+    /// Games in modes that are only for the moment aren't kept to resume later.
+    pub fn saved(&self) -> bool {
+        !matches!(self, Mode::Zen | Mode::Puzzle { .. } | Mode::Sandbox | Mode::Finesse { .. } | Mode::Versus)
+    }
+
+    /// Replays start from an empty board, which puzzles and the sandbox don't,
+    /// and hold a single player's inputs.
+    pub fn replayed(&self) -> bool {
+        !matches!(self, Mode::Puzzle { .. } | Mode::Sandbox | Mode::Versus)
+    }
+
+    pub fn holds(&self) -> bool {
+        match self {
+            Mode::Puzzle { hold, .. } => *hold,
+            Mode::Finesse { .. } => false,
+            _ => true,
+        }
+    }
+
+    pub fn levels_up(&self) -> bool {
+        matches!(self, Mode::Marathon { .. })
+    }
+
+    pub fn goal_reached(&self, game: &Game) -> bool {
+        match self {
+            Mode::Marathon { goal } => goal.is_some_and(|lines| game.lines_cleared >= lines),
+            Mode::Zen | Mode::Sandbox => false,
+            Mode::Sprint { lines } => game.lines_cleared >= *lines,
+            Mode::Ultra { seconds } => game.time_millis() >= *seconds as u64 * 1000,
+            Mode::Dig { .. } => game.garbage_left == 0 && game.garbage_rows() == 0,
+            Mode::Puzzle { goal, .. } => goal.reached(game),
+            Mode::Master => game.level >= MASTER_LEVELS,
+            Mode::Finesse { targets } => game.pieces >= *targets,
+            Mode::Survival { .. } | Mode::Versus => false,
+        }
+    }
+
+    /// The time shown while playing, counting down when there is a time limit.
+    pub fn shown_time(&self, game: &Game) -> u64 {
+        match self {
+            Mode::Ultra { seconds } => (*seconds as u64 * 1000).saturating_sub(game.time_millis()),
+            _ => game.time_millis(),
+        }
+    }
+
+    /// Name under which personal bests are kept, each goal has its own.
+    pub fn record_key(&self) -> Option<String> {
+        match self {
+            Mode::Marathon { goal } => goal.map(|lines| format!("marathon-{}", lines)),
+            Mode::Sprint { lines } => Some(format!("sprint-{}", lines)),
+            Mode::Ultra { seconds } => Some(format!("ultra-{}", seconds)),
+            Mode::Dig { rows, messiness, visible } => Some(format!("dig-{}-{}-{}", rows, messiness, visible)),
+            Mode::Master => Some("master".to_string()),
+            Mode::Finesse { targets } => Some(format!("finesse-{}", targets)),
+            Mode::Survival { messiness } => Some(format!("survival-{}", messiness)),
+            Mode::Zen | Mode::Puzzle { .. } | Mode::Sandbox | Mode::Versus => None,
+        }
+    }
+
+    /// What the game is ranked by and whether lower is better.
+    fn result(&self, game: &Game) -> (u64, bool) {
+        match self {
+            Mode::Marathon { .. } | Mode::Ultra { .. } | Mode::Master | Mode::Survival { .. } | Mode::Versus => (game.score as u64, false),
+            Mode::Sprint { .. } | Mode::Dig { .. } | Mode::Zen | Mode::Puzzle { .. } | Mode::Sandbox => (game.time_millis(), true),
+            Mode::Finesse { .. } => (game.finesse_faults as u64, true),
+        }
+    }
+
+    fn format_result(&self, value: u64) -> String {
+        let text = match self {
+            Mode::Marathon { .. } | Mode::Ultra { .. } | Mode::Master | Mode::Survival { .. } | Mode::Versus => value.to_string(),
+            Mode::Sprint { .. } | Mode::Dig { .. } | Mode::Zen | Mode::Puzzle { .. } | Mode::Sandbox => format_time(value),
+            Mode::Finesse { .. } if value == 1 => "1 fault".to_string(),
+            Mode::Finesse { .. } => format!("{} faults", value),
+        };
+        format!("{:^10}", text)
+    }
+}
+
+/// Results once the goal is reached, a record is kept when the game beat the personal best.
+pub fn show_results(game: &Game) {
+    let mode = game.rules.mode;
+    let (result, lower_is_better) = mode.result(game);
+    let title = match mode {
+        Mode::Marathon { .. } | Mode::Master => blink!("CONGRATS! ", GREEN),
+        Mode::Ultra { .. } => blink!(" TIME UP! ", GREEN),
+        Mode::Survival { .. } => blink!("GAME OVER!", RED),
+        Mode::Sprint { .. } | Mode::Dig { .. } | Mode::Zen | Mode::Puzzle { .. } | Mode::Sandbox | Mode::Finesse { .. }
+        | Mode::Versus => {
+            blink!(" FINISHED ", GREEN)
+        }
+    };
+    let mut messages = vec![
+        (7, title),
+        (9, color!(mode.format_result(result), WHITE)),
+    ];
+    match mode {
+        // fewer pieces means cleaner digging, shown next to the time it is ranked by
+        Mode::Dig { .. } => messages.push((10, color!(format!("{:^10}", format!("{} pieces", game.pieces)), WHITE))),
+        Mode::Finesse { .. } | Mode::Survival { .. } => {
+            messages.push((10, color!(format!("{:^10}", format_time(game.time_millis())), WHITE)))
+        }
+        _ => {}
+    }
+    let mut problem = None;
+    match game.rules.record_key() {
+        Some(key) if !game.assisted => {
+            let mut records = Records::load();
+            let best = records.best(&key);
+            match records.submit(&key, result, lower_is_better) {
+                Ok(true) => messages.push((11, blink!("NEW BEST! ", YELLOW))),
+                Ok(false) => {
+                    messages.push((11, color!("   best   ", BLUE)));
+                    messages.push((12, color!(mode.format_result(best.unwrap_or_default()), BLUE)));
+                }
+                Err(error) => problem = Some(error),
+            }
+        }
+        Some(_) => messages.push((11, color!("no record ", BLUE))),
+        None => {}
+    }
+    game.draw_with_messages(&messages);
+    line_in_color(&format!("Seed {}", game.seed), BLUE);
+    if let Some(error) = problem {
+        line_in_color(&format!("Could not keep record, {}", error), RED);
+    }
+}
+
+/// Time and score of every level played in a marathon, or every section of a hundred levels in master,
+/// the last one up to the end of the game.
+pub fn show_splits(game: &Game) {
+    let (name, label): (&str, fn(usize) -> String) = match game.rules.mode {
+        Mode::Master => ("Section", |section| format!("{}-{}", section * 100, section * 100 + 99)),
+        _ => ("Level", |level| (level + 1).to_string()),
+    };
+    let width = name.len();
+    line_in_color(&format!("{:>width$} {:>9} {:>6}", name, "Time", "Score"), BLUE);
+    let end = (game.millis, game.score);
+    let mut start = (0, 0);
+    for (index, split) in game.splits.iter().chain([&end]).enumerate() {
+        let (millis, score) = (split.0 - start.0, split.1 - start.1);
+        overwrite();
+        println!("{:>width$} {:>9} {:6}", label(index), format_time(millis), score);
+        start = *split;
+    }
+}
+
+/// Game time as `m:ss.mmm`.
+pub fn format_time(millis: u64) -> String {
+    format!("{}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
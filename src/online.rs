@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::mem;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::garbage::Holes;
+use crate::modes::Mode;
+use crate::pacer::Pacer;
+use crate::protocol::{Delay, Link, Message, PROTOCOL_VERSION};
+use crate::rules::Rules;
+use crate::terminal::*;
+use crate::terminal::Color::*;
+use crate::versus::{draw_versus, round_messages, Round, ROUND_PAUSE};
+use crate::{check_ctrl_c, ctrl_c_channel, input_channel, move_for_key, Game, Move, DRAW_SPEED};
+
+// frames between making a move and playing it, the time it has to reach the other player
+const INPUT_DELAY: u64 = 3;
+// frames between checks that both players still play the same games
+const CHECK_FRAMES: u64 = 1000 / DRAW_SPEED;
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+// waiting this long for the other player shows on screen
+const WAIT_NOTICE: Duration = Duration::from_millis(500);
+
+/// A match agreed on with the other player, the host plays on the left.
+pub struct Session {
+    link: Link,
+    side: usize,
+    seed: u64,
+    rounds: u32,
+    holes: Holes,
+}
+
+/// Waits for the other player to connect on `listener`, then tells them what to play.
+pub fn host(listener: TcpListener, seed: u64, rounds: u32, holes: Holes, delay: Delay) -> Result<Session, String> {
+    let port = listener.local_addr().map_err(|error| error.to_string())?.port();
+    line_in_color(&format!("Waiting for the other player on port {}", port), YELLOW);
+    let (stream, address) = listener.accept().map_err(|error| format!("cannot accept the other player: {}", error))?;
+    line_in_color(&format!("{} joined", address), YELLOW);
+    let mut link = Link::new(stream, delay)?;
+    link.send(&Message::Hello { version: PROTOCOL_VERSION });
+    link.send(&Message::Match { seed, rounds, holes });
+    expect_hello(&link)?;
+    Ok(Session { link, side: 0, seed, rounds, holes })
+}
+
+pub fn join(address: &str, delay: Delay) -> Result<Session, String> {
+    let stream = TcpStream::connect(address).map_err(|error| format!("cannot connect to {}: {}", address, error))?;
+    let mut link = Link::new(stream, delay)?;
+    link.send(&Message::Hello { version: PROTOCOL_VERSION });
+    expect_hello(&link)?;
+    match link.receive(ANSWER_TIMEOUT)? {
+        Message::Match { seed, rounds, holes } => Ok(Session { link, side: 1, seed, rounds, holes }),
+        _ => Err("the host didn't say what to play".to_string()),
+    }
+}
+
+fn expect_hello(link: &Link) -> Result<(), String> {
+    match link.receive(ANSWER_TIMEOUT)? {
+        Message::Hello { version } if version == PROTOCOL_VERSION => Ok(()),
+        Message::Hello { version } => Err(format!(
+            "the other player uses version {} of the protocol, this is version {}", version, PROTOCOL_VERSION)),
+        _ => Err("the other player doesn't speak the protocol".to_string()),
+    }
+}
+
+/// Moves of both players by round and frame, and the checks of the other player's game,
+/// compared once both sides played the frame.
+#[derive(Default)]
+struct Lockstep {
+    local: HashMap<(u32, u64), Vec<Move>>,
+    remote: HashMap<(u32, u64), Vec<Move>>,
+    // state hash and garbage sent so far, as the other player sent them and as played here
+    checks: HashMap<(u32, u64), (u64, u32)>,
+    mirrored: HashMap<(u32, u64), (u64, u32)>,
+}
+
+impl Lockstep {
+    /// Takes in a message if one comes within `timeout`, returns whether one did.
+    fn receive(&mut self, link: &Link, timeout: Duration) -> Result<bool, String> {
+        let message = match link.incoming.recv_timeout(timeout) {
+            Ok(message) => message?,
+            Err(RecvTimeoutError::Timeout) => return Ok(false),
+            Err(RecvTimeoutError::Disconnected) => return Err("the other player left".to_string()),
+        };
+        match message {
+            Message::Frame { round, frame, moves } => {
+                self.remote.insert((round, frame), moves);
+            }
+            Message::Check { round, frame, hash, garbage } => {
+                self.checks.insert((round, frame), (hash, garbage));
+                self.compare((round, frame))?;
+            }
+            Message::Bye => return Err("the other player left".to_string()),
+            Message::Hello { .. } | Message::Match { .. } => return Err("the other player started over".to_string()),
+        }
+        Ok(true)
+    }
+
+    fn compare(&mut self, key: (u32, u64)) -> Result<(), String> {
+        if let (Some(theirs), Some(ours)) = (self.checks.get(&key), self.mirrored.get(&key)) {
+            if theirs != ours {
+                return Err(format!("out of sync with the other player at frame {} of round {}", key.1, key.0 + 1));
+            }
+            self.checks.remove(&key);
+            self.mirrored.remove(&key);
+        }
+        Ok(())
+    }
+}
+
+/// Plays a match with the other player, both play the two games from the same seed with each
+/// other's moves, which keeps the games the same as long as the moves arrive.
+pub fn play_online(mut session: Session) {
+    let input_rx = input_channel(Some);
+    let ctrl_c_rx = ctrl_c_channel();
+    let rules = Rules { mode: Mode::Versus, holes: session.holes, ..Rules::default() };
+    let (side, other) = (session.side, 1 - session.side);
+    let mut lockstep = Lockstep::default();
+    let mut wins = [0; 2];
+    line_in_color(&format!("Versus over the network, best of {}", session.rounds), YELLOW);
+    create_game_screen(25);
+    let mut round = 0;
+    while wins.iter().all(|won| won * 2 <= session.rounds) {
+        // both players get the same blocks, other ones every round
+        let seed = session.seed.wrapping_add(round as u64);
+        let mut games = [Game::new(seed, rules), Game::new(seed, rules)];
+        let mut titles = [String::new(), String::new()];
+        titles[side] = format!("You  wins {}", wins[side]);
+        titles[other] = format!("Opponent  wins {}", wins[other]);
+        let result = play_round(&mut session, &mut lockstep, round, &mut games, &titles, &input_rx, &ctrl_c_rx);
+        let outcome = match result {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                line_in_color(&format!("Match over, {}", error), RED);
+                return;
+            }
+        };
+        if let Round::Won(winner) = outcome {
+            wins[winner] += 1;
+        }
+        match round_messages(&outcome) {
+            Some(messages) => draw_versus(&games, &titles, false, &messages),
+            None => return,
+        }
+        overwrite();
+        sleep(ROUND_PAUSE);
+        while input_rx.try_recv().is_ok() {}
+        round += 1;
+    }
+    let result = match wins[side] > wins[other] {
+        true => "You win",
+        false => "The other player wins",
+    };
+    line_in_color(&format!("{} the match {} to {}", result, wins[0].max(wins[1]), wins[0].min(wins[1])), BLUE);
+}
+
+/// Plays the frames of a round as soon as the moves of both players are in, every move is played
+/// `INPUT_DELAY` frames after it was made so it has time to reach the other player.
+fn play_round(session: &mut Session, lockstep: &mut Lockstep, round: u32, games: &mut [Game; 2], titles: &[String; 2],
+              input_rx: &Receiver<Key>, ctrl_c_rx: &Receiver<()>) -> Result<Round, String> {
+    let mut play = RoundPlay::start(session, lockstep, round);
+    let mut pacer = Pacer::start();
+    let mut typed = Vec::new();
+    loop {
+        while let Ok(key) = input_rx.try_recv() {
+            match move_for_key(key) {
+                Some(Move::Quit) => {
+                    session.link.send(&Message::Bye);
+                    return Ok(Round::Quit);
+                }
+                Some(mov) if mov.plays_game() => typed.push(mov),
+                _ => {}
+            }
+        }
+        if check_ctrl_c(ctrl_c_rx) {
+            session.link.send(&Message::Bye);
+            return Ok(Round::Quit);
+        }
+
+        let alive = match play.play_frame(session, lockstep, games, &mut typed, Duration::from_millis(DRAW_SPEED))? {
+            Progress::Played(alive) => alive,
+            Progress::Waiting(waited) => {
+                draw(games, titles, waited > WAIT_NOTICE);
+                pacer.restart();
+                continue;
+            }
+        };
+        match alive {
+            [true, true] => {}
+            [false, false] => return Ok(Round::Draw),
+            [true, false] => return Ok(Round::Won(0)),
+            [false, true] => return Ok(Round::Won(1)),
+        }
+        draw(games, titles, false);
+
+        pacer.wait();
+    }
+}
+
+/// Where a round is at apart from the keys, the screen and the clock, so its frames play the same
+/// without a terminal.
+struct RoundPlay {
+    round: u32,
+    frame: u64,
+    // garbage each player sent so far
+    garbage: [u32; 2],
+    waiting_since: Option<Instant>,
+}
+
+enum Progress {
+    Played([bool; 2]),
+    // the other player's moves aren't in yet, for this long
+    Waiting(Duration),
+}
+
+impl RoundPlay {
+    /// Sends the first moves of the round, none, for the frames before the first moves made can be played.
+    fn start(session: &mut Session, lockstep: &mut Lockstep, round: u32) -> RoundPlay {
+        lockstep.remote.retain(|(played, _), _| *played >= round);
+        for frame in 0..INPUT_DELAY {
+            session.link.send(&Message::Frame { round, frame, moves: Vec::new() });
+            lockstep.local.insert((round, frame), Vec::new());
+        }
+        RoundPlay { round, frame: 0, garbage: [0; 2], waiting_since: None }
+    }
+
+    /// Plays the next frame of both games once the other player's moves for it are in, and sends the
+    /// moves `typed` since the last frame to be played `INPUT_DELAY` frames later. Waits up to `wait`
+    /// for the other player's moves when they aren't in, returns which games go on once played.
+    fn play_frame(&mut self, session: &mut Session, lockstep: &mut Lockstep, games: &mut [Game; 2], typed: &mut Vec<Move>,
+                  wait: Duration) -> Result<Progress, String> {
+        let (round, frame) = (self.round, self.frame);
+        let (side, other) = (session.side, 1 - session.side);
+        let Some(remote) = lockstep.remote.remove(&(round, frame)) else {
+            let since = *self.waiting_since.get_or_insert_with(Instant::now);
+            if since.elapsed() > ANSWER_TIMEOUT {
+                return Err("no answer from the other player".to_string());
+            }
+            lockstep.receive(&session.link, wait)?;
+            return Ok(Progress::Waiting(since.elapsed()));
+        };
+        self.waiting_since = None;
+
+        session.link.send(&Message::Frame { round, frame: frame + INPUT_DELAY, moves: typed.clone() });
+        lockstep.local.insert((round, frame + INPUT_DELAY), mem::take(typed));
+        let mut moves = [Vec::new(), Vec::new()];
+        moves[side] = lockstep.local.remove(&(round, frame)).unwrap_or_default();
+        moves[other] = remote;
+        for (game, moves) in games.iter_mut().zip(&moves) {
+            for mov in moves {
+                game.apply(*mov);
+            }
+        }
+        let alive = [games[0].tick(), games[1].tick()];
+        for player in 0..2 {
+            let sent = mem::take(&mut games[player].outgoing);
+            games[1 - player].receive_garbage(sent);
+            self.garbage[player] += sent;
+        }
+        if frame.is_multiple_of(CHECK_FRAMES) {
+            session.link.send(&Message::Check { round, frame, hash: games[side].state_hash(), garbage: self.garbage[side] });
+            lockstep.mirrored.insert((round, frame), (games[other].state_hash(), self.garbage[other]));
+            lockstep.compare((round, frame))?;
+        }
+        self.frame += 1;
+        while lockstep.receive(&session.link, Duration::ZERO)? {}
+        Ok(Progress::Played(alive))
+    }
+}
+
+fn draw(games: &[Game; 2], titles: &[String; 2], waiting: bool) {
+    draw_versus(games, titles, false, &[Vec::new(), Vec::new()]);
+    overwrite();
+    if waiting {
+        print!("{}", color!("       Waiting for the other player...", BLUE));
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::COUNTDOWN;
+
+    const DELAY: Delay = Delay { latency: Duration::from_millis(15), jitter: Duration::from_millis(10) };
+    // moves count once the countdown is over, then there are a few checks
+    const FRAMES: u64 = COUNTDOWN + 3 * CHECK_FRAMES + 1;
+
+    fn connect() -> (Session, Session) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("cannot listen");
+        let address = listener.local_addr().expect("no address").to_string();
+        let hosting = thread::spawn(move || host(listener, 7, 3, Holes::default(), DELAY));
+        let joined = join(&address, DELAY).expect("cannot join the host");
+        (hosting.join().unwrap().expect("cannot host"), joined)
+    }
+
+    /// Moves each side makes, different on both sides and turning, moving and holding.
+    fn scripted(side: usize, frame: u64) -> Vec<Move> {
+        match (side, frame % 9) {
+            (0, 1) => vec![Move::Left],
+            (1, 1) => vec![Move::Right, Move::Right],
+            (_, 4) => vec![Move::Up],
+            (0, 6) if frame > COUNTDOWN + 30 => vec![Move::Hold],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Plays the first frames of a round with the scripted moves. `tampered` is a frame whose moves are
+    /// sent as scripted but played with a hold more, as a game out of sync would.
+    fn play_scripted(mut session: Session, tampered: Option<u64>) -> Result<(), String> {
+        let rules = Rules { mode: Mode::Versus, ..Rules::default() };
+        let mut games = [Game::new(session.seed, rules), Game::new(session.seed, rules)];
+        let mut lockstep = Lockstep::default();
+        let mut play = RoundPlay::start(&mut session, &mut lockstep, 0);
+        while play.frame < FRAMES {
+            let frame = play.frame;
+            let mut typed = scripted(session.side, frame);
+            while let Progress::Waiting(_) = play.play_frame(&mut session, &mut lockstep, &mut games, &mut typed, ANSWER_TIMEOUT)? {}
+            if tampered == Some(frame) {
+                lockstep.local.insert((0, frame + INPUT_DELAY), vec![Move::Hold]);
+            }
+        }
+        // the other player's last checks may still be on the way
+        while !lockstep.mirrored.is_empty() && lockstep.receive(&session.link, ANSWER_TIMEOUT)? {}
+        match lockstep.mirrored.is_empty() {
+            true => Ok(()),
+            false => Err("checks left unanswered".to_string()),
+        }
+    }
+
+    #[test]
+    fn games_stay_the_same_on_a_slow_network() {
+        let (hosted, joined) = connect();
+        let guest = thread::spawn(move || play_scripted(joined, None));
+        assert_eq!(play_scripted(hosted, None), Ok(()));
+        assert_eq!(guest.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn a_changed_move_is_reported_as_out_of_sync() {
+        let (hosted, joined) = connect();
+        // the guest plays a move the host never hears of
+        let guest = thread::spawn(move || play_scripted(joined, Some(COUNTDOWN + 10)));
+        let error = play_scripted(hosted, None).unwrap_err();
+        let checked = (COUNTDOWN + 10 + INPUT_DELAY).next_multiple_of(CHECK_FRAMES);
+        assert_eq!(error, format!("out of sync with the other player at frame {} of round 1", checked));
+        guest.join().unwrap().ok();
+    }
+}
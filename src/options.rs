@@ -0,0 +1,224 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::garbage::Holes;
+use crate::modes::{Mode, Settings};
+use crate::protocol::Delay;
+use crate::rules::Rules;
+use crate::DRAW_SPEED;
+
+pub const USAGE: &str = "\
+Usage: tetris [options]
+       tetris verify <replay>   check the result claimed in a replay, exits with 1 if it doesn't match
+
+Options:
+  --mode <mode>   marathon (default), sprint, ultra, zen, dig, master, sandbox, finesse or survival,
+                  the sandbox sets up a board to play from in an editor,
+                  finesse drills placing blocks on targets with as few turns and moves as possible,
+                  survival raises garbage ever faster and scores the seconds survived plus the lines
+  --goal <lines>  lines to clear, 40 by default in sprint, marathon is endless unless given one (150 or 200),
+                  rows of garbage to dig through in dig, 10 by default, targets in finesse, 20 by default
+  --time <secs>   time limit in ultra, 120 by default
+  --messiness <percent>
+                  chance the hole moves from one garbage row to the next, 100 by default in dig, 30 in survival
+  --visible <rows> garbage rows on the board at once in dig, more come up as they are cleared, 10 by default
+  --load <file>   resume the game saved in <file>
+  --seed <number> seed for the random blocks, the same seed gives the same blocks
+  --replay <file> watch a recorded game
+  --puzzles <file> play the puzzles in a pack, one after the other
+  --practice      allow undoing (z) and redoing (x) placements, the game won't count for records
+  --invisible     placed blocks disappear from view, the stack shows again at game over
+  --fade <ms>     time placed blocks stay in view before they disappear, makes the blocks disappear
+  --outline <ms>  show the outline of a disappeared stack for a moment after clearing lines
+  --big           play with blocks of twice the size on a board of 5 by 10 cells
+  --finesse       count placements made with more turns and moves than needed as faults
+  --versus <rounds> two players on one keyboard, best of <rounds>,
+                  the first plays with w a s d and c to hold, the second with the arrows and 0 to hold
+  --holes <style> where the holes of garbage from the opponent go, batch (default) gives every batch
+                  one hole, row every row, :<percent> sets the chance the hole moves, as in row:50,
+                  30 by default for batches and 100 for rows, over the network the host's style
+                  holds for both players and in a battle royale the server's
+  --host <port>   play versus with another player over the network, waiting for them on <port>,
+                  best of 3 unless --versus gives the rounds
+  --connect <address>
+                  join the versus match hosted at <address>, as in localhost:4000
+  --latency <ms>  hold back what is sent to the other player, to try a slow network
+  --jitter <ms>   hold back every message up to this much more on top of the latency
+  --broadcast <port>
+                  let others watch the game, connecting on <port>, only a game of your own
+                  rather than versus, puzzles, the sandbox, a server or a replay
+  --watch <address>
+                  watch the game broadcast at <address>, as in localhost:4000
+  --serve <port>  run a server anyone can play on with telnet or nc, each connection plays its own game
+                  with the mode and rules given, server games aren't saved or recorded
+  --royale <port> run a battle royale server, 3 to 99 players join with telnet and attack each other
+                  until one is left, enter starts a match once enough are in,
+                  players pick who they attack with 1 random, 2 attackers, 3 badges or 4 KOs
+  --help          show this help";
+
+pub struct Options {
+    pub mode: Mode,
+    pub load: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub replay: Option<PathBuf>,
+    pub puzzles: Option<PathBuf>,
+    pub verify: Option<PathBuf>,
+    pub practice: bool,
+    pub invisible: bool,
+    // milliseconds
+    pub fade: u64,
+    pub outline: u64,
+    pub big: bool,
+    pub finesse: bool,
+    pub holes: Holes,
+    // rounds of a versus match
+    pub versus: Option<u32>,
+    pub host: Option<u16>,
+    pub connect: Option<String>,
+    // milliseconds
+    pub latency: u64,
+    pub jitter: u64,
+    pub broadcast: Option<u16>,
+    pub watch: Option<String>,
+    pub serve: Option<u16>,
+    pub royale: Option<u16>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            mode: Mode::Marathon { goal: None },
+            load: None,
+            seed: None,
+            replay: None,
+            puzzles: None,
+            verify: None,
+            practice: false,
+            invisible: false,
+            fade: 0,
+            outline: 0,
+            big: false,
+            finesse: false,
+            holes: Holes::default(),
+            versus: None,
+            host: None,
+            connect: None,
+            latency: 0,
+            jitter: 0,
+            broadcast: None,
+            watch: None,
+            serve: None,
+            royale: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args() -> Result<Options, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn rules(&self) -> Rules {
+        Rules {
+            mode: self.mode,
+            practice: self.practice,
+            invisible: self.invisible,
+            fade: self.fade / DRAW_SPEED,
+            outline: self.outline / DRAW_SPEED,
+            big: self.big,
+            finesse: self.finesse,
+            holes: self.holes,
+            ..Rules::default()
+        }
+    }
+
+    /// Whether the options pick the game to play, rather than leaving it to the saved game.
+    pub fn defines_game(&self) -> bool {
+        self.seed.is_some() || self.rules() != Options::default().rules()
+    }
+
+    pub fn delay(&self) -> Delay {
+        Delay {
+            latency: Duration::from_millis(self.latency),
+            jitter: Duration::from_millis(self.jitter),
+        }
+    }
+
+    pub fn parse(mut args: impl Iterator<Item=String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut mode = "marathon".to_string();
+        let mut settings = Settings::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--mode" => mode = value(&arg, args.next())?,
+                "--goal" => settings.goal = Some(number(&arg, args.next())?),
+                "--time" => settings.time = Some(number(&arg, args.next())?),
+                "--messiness" => settings.messiness = Some(number(&arg, args.next())?),
+                "--visible" => settings.visible = Some(number(&arg, args.next())?),
+                "--load" => options.load = Some(value(&arg, args.next())?.into()),
+                "--seed" => options.seed = Some(number(&arg, args.next())?),
+                "--replay" => options.replay = Some(value(&arg, args.next())?.into()),
+                "--puzzles" => options.puzzles = Some(value(&arg, args.next())?.into()),
+                "verify" => options.verify = Some(value(&arg, args.next())?.into()),
+                "--practice" => options.practice = true,
+                "--invisible" => options.invisible = true,
+                "--fade" => {
+                    options.invisible = true;
+                    options.fade = number(&arg, args.next())?;
+                }
+                "--outline" => options.outline = number(&arg, args.next())?,
+                "--big" => options.big = true,
+                "--host" => options.host = Some(number(&arg, args.next())?),
+                "--connect" => options.connect = Some(value(&arg, args.next())?),
+                "--latency" => options.latency = number(&arg, args.next())?,
+                "--jitter" => options.jitter = number(&arg, args.next())?,
+                "--broadcast" => options.broadcast = Some(number(&arg, args.next())?),
+                "--watch" => options.watch = Some(value(&arg, args.next())?),
+                "--serve" => options.serve = Some(number(&arg, args.next())?),
+                "--royale" => options.royale = Some(number(&arg, args.next())?),
+                "--finesse" => options.finesse = true,
+                "--holes" => options.holes = Holes::parse(&value(&arg, args.next())?)?,
+                "--versus" => {
+                    let rounds = number(&arg, args.next())?;
+                    if rounds == 0 {
+                        return Err("a versus match needs at least one round".to_string());
+                    }
+                    options.versus = Some(rounds);
+                }
+                "--help" | "-h" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+        options.mode = Mode::parse(&mode, &settings)?;
+        if options.broadcast.is_some() {
+            // only a game of the player's own is broadcast, these would go on without it
+            let others = [
+                ("--versus", options.versus.is_some()),
+                ("--host", options.host.is_some()),
+                ("--connect", options.connect.is_some()),
+                ("--mode sandbox", options.mode == Mode::Sandbox),
+                ("--puzzles", options.puzzles.is_some()),
+                ("--serve", options.serve.is_some()),
+                ("--royale", options.royale.is_some()),
+                ("--watch", options.watch.is_some()),
+                ("--replay", options.replay.is_some()),
+            ];
+            if let Some((other, _)) = others.iter().find(|(_, given)| *given) {
+                return Err(format!("--broadcast can't be combined with {}", other));
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn number<T: std::str::FromStr>(option: &str, text: Option<String>) -> Result<T, String> {
+    let text = value(option, text)?;
+    text.parse().map_err(|_| format!("{} needs a number, got {}", option, text))
+}
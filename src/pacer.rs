@@ -0,0 +1,61 @@
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::DRAW_SPEED;
+
+/// Keeps the frames of a game loop to the clock so the game time matches the real time.
+pub struct Pacer {
+    next_frame: Instant,
+}
+
+impl Pacer {
+    pub fn start() -> Pacer {
+        Pacer { next_frame: Instant::now() }
+    }
+
+    /// Sleeps until the next frame is due, one that fell behind, by pausing or celebrating,
+    /// doesn't rush the frames after it to catch up.
+    pub fn wait(&mut self) {
+        self.next_frame += Duration::from_millis(DRAW_SPEED);
+        let now = Instant::now();
+        if self.next_frame > now {
+            sleep(self.next_frame - now);
+        } else {
+            self.next_frame = now;
+        }
+    }
+
+    /// Counts the next frame from now, after waiting on something other than the clock.
+    pub fn restart(&mut self) {
+        self.next_frame = Instant::now();
+    }
+}
+
+/// Measures the real time frames take to the millisecond, leaving out what it's told to skip.
+pub struct Stopwatch {
+    last: Instant,
+    measured: Duration,
+    // milliseconds handed out so far, the fractions left over go to later frames
+    counted: u64,
+}
+
+impl Stopwatch {
+    pub fn start() -> Stopwatch {
+        Stopwatch { last: Instant::now(), measured: Duration::ZERO, counted: 0 }
+    }
+
+    /// Milliseconds since the previous frame.
+    pub fn lap(&mut self) -> u64 {
+        let now = Instant::now();
+        self.measured += now - self.last;
+        self.last = now;
+        let millis = self.measured.as_millis() as u64 - self.counted;
+        self.counted += millis;
+        millis
+    }
+
+    /// Leaves the time since the previous frame out, as while paused or celebrating.
+    pub fn skip(&mut self) {
+        self.last = Instant::now();
+    }
+}
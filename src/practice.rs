@@ -0,0 +1,51 @@
+use crate::Game;
+
+/// Game states after every placement in practice, so misdrops can be taken back and replayed.
+pub struct Practice {
+    history: Vec<Game>,
+    current: usize,
+}
+
+impl Practice {
+    pub fn new(game: &Game) -> Practice {
+        Practice {
+            history: vec![game.clone()],
+            current: 0,
+        }
+    }
+
+    /// Remembers the game once a block was placed and the next one is in play,
+    /// placing after undoing forgets the placements that could be redone.
+    pub fn record(&mut self, game: &Game) {
+        if game.spawning() || game.pieces == self.history[self.current].pieces {
+            return;
+        }
+        self.history.truncate(self.current + 1);
+        self.history.push(game.clone());
+        self.current += 1;
+    }
+
+    pub fn undo(&mut self, game: &mut Game) -> bool {
+        if self.current == 0 {
+            return false;
+        }
+        self.current -= 1;
+        self.restore(game);
+        true
+    }
+
+    pub fn redo(&mut self, game: &mut Game) -> bool {
+        if self.current + 1 >= self.history.len() {
+            return false;
+        }
+        self.current += 1;
+        self.restore(game);
+        true
+    }
+
+    fn restore(&self, game: &mut Game) {
+        *game = self.history[self.current].clone();
+        // taking back placements makes the game ineligible for records
+        game.assisted = true;
+    }
+}
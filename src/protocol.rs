@@ -0,0 +1,184 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::garbage::Holes;
+use crate::Move;
+
+pub const PROTOCOL: &str = "tetris-versus";
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// What the players of a game over the network tell each other, a line of text each:
+///
+/// ```text
+/// hello tetris-versus 2        (both, first thing on connecting)
+/// match 1234 3 batch:30        (host: seed, rounds and where the holes of garbage go)
+/// frame 0 120 ul               (round, frame and the moves made in it, - for none)
+/// check 0 120 1f00aa2e3b4c5d6e 4 (round, frame, state hash of the sender's game and garbage it sent so far)
+/// bye                          (leaving the match)
+/// ```
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Hello { version: u32 },
+    Match { seed: u64, rounds: u32, holes: Holes },
+    Frame { round: u32, frame: u64, moves: Vec<Move> },
+    Check { round: u32, frame: u64, hash: u64, garbage: u32 },
+    Bye,
+}
+
+impl Message {
+    pub fn encode(&self) -> String {
+        match self {
+            Message::Hello { version } => format!("hello {} {}", PROTOCOL, version),
+            Message::Match { seed, rounds, holes } => format!("match {} {} {}", seed, rounds, holes.encode()),
+            Message::Frame { round, frame, moves } => {
+                let moves = match moves.is_empty() {
+                    true => "-".to_string(),
+                    false => moves.iter().map(Move::code).collect(),
+                };
+                format!("frame {} {} {}", round, frame, moves)
+            }
+            Message::Check { round, frame, hash, garbage } => format!("check {} {} {:016x} {}", round, frame, hash, garbage),
+            Message::Bye => "bye".to_string(),
+        }
+    }
+
+    pub fn decode(line: &str) -> Result<Message, String> {
+        let invalid = || format!("invalid message: {}", line);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |index: usize| words.get(index).and_then(|word| word.parse::<u64>().ok()).ok_or_else(invalid);
+        match words.first().copied() {
+            Some("hello") if words.get(1) == Some(&PROTOCOL) => Ok(Message::Hello { version: number(2)? as u32 }),
+            Some("match") => Ok(Message::Match {
+                seed: number(1)?,
+                rounds: number(2)? as u32,
+                holes: Holes::parse(words.get(3).copied().ok_or_else(invalid)?)?,
+            }),
+            Some("frame") => {
+                let moves = match words.get(3).copied().ok_or_else(invalid)? {
+                    "-" => Vec::new(),
+                    codes => codes.chars().map(|code| Move::from_code(code).ok_or_else(invalid)).collect::<Result<_, _>>()?,
+                };
+                Ok(Message::Frame { round: number(1)? as u32, frame: number(2)?, moves })
+            }
+            Some("check") => Ok(Message::Check {
+                round: number(1)? as u32,
+                frame: number(2)?,
+                hash: words.get(3).and_then(|hash| u64::from_str_radix(hash, 16).ok()).ok_or_else(invalid)?,
+                garbage: number(4)? as u32,
+            }),
+            Some("bye") => Ok(Message::Bye),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Listens for connections on `port` from anywhere.
+pub fn listen(port: u16) -> Result<TcpListener, String> {
+    TcpListener::bind(("0.0.0.0", port)).map_err(|error| format!("cannot listen on port {}: {}", port, error))
+}
+
+/// Writes what's sent on the channel to the connection on a thread of its own, so a slow reader
+/// holds no one up. The thread ends once the channel closes or the connection fails.
+pub fn spawn_writer<T: AsRef<[u8]> + Send + 'static>(mut stream: TcpStream) -> (Sender<T>, JoinHandle<()>) {
+    stream.set_nodelay(true).ok();
+    let (sender, received) = channel::<T>();
+    let writer = thread::spawn(move || {
+        for bytes in received {
+            if stream.write_all(bytes.as_ref()).is_err() {
+                break;
+            }
+        }
+    });
+    (sender, writer)
+}
+
+/// Extra time messages take to go out, to try games over a slow network on one machine.
+#[derive(Copy, Clone, Default)]
+pub struct Delay {
+    pub latency: Duration,
+    // each message is held back up to this much more, still going out in order
+    pub jitter: Duration,
+}
+
+/// Connection to the other player, messages are sent and read on threads of their own.
+pub struct Link {
+    outgoing: Sender<(Instant, String)>,
+    pub incoming: Receiver<Result<Message, String>>,
+    delay: Delay,
+    last_due: Instant,
+}
+
+impl Link {
+    pub fn new(stream: TcpStream, delay: Delay) -> Result<Link, String> {
+        stream.set_nodelay(true).map_err(|error| error.to_string())?;
+        let mut writer = stream.try_clone().map_err(|error| error.to_string())?;
+        let (outgoing, outgoing_rx) = channel::<(Instant, String)>();
+        thread::spawn(move || {
+            for (due, line) in outgoing_rx {
+                sleep(due.saturating_duration_since(Instant::now()));
+                if writeln!(writer, "{}", line).is_err() {
+                    break;
+                }
+            }
+        });
+        let (incoming_tx, incoming) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if incoming_tx.send(Message::decode(&line)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Link { outgoing, incoming, delay, last_due: Instant::now() })
+    }
+
+    pub fn send(&mut self, message: &Message) {
+        let jitter = match self.delay.jitter.is_zero() {
+            true => Duration::ZERO,
+            false => rand::thread_rng().gen_range(Duration::ZERO..=self.delay.jitter),
+        };
+        self.last_due = self.last_due.max(Instant::now() + self.delay.latency + jitter);
+        // a closed connection shows on the incoming side
+        self.outgoing.send((self.last_due, message.encode())).ok();
+    }
+
+    /// Waits for the next message, an error when it's unreadable or the other player is gone.
+    pub fn receive(&self, timeout: Duration) -> Result<Message, String> {
+        self.incoming.recv_timeout(timeout).map_err(|_| "no answer from the other player".to_string())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_read_back_as_written() {
+        let messages = [
+            Message::Hello { version: PROTOCOL_VERSION },
+            Message::Match { seed: u64::MAX, rounds: 5, holes: Holes::Row(50) },
+            Message::Frame { round: 2, frame: 120, moves: Vec::new() },
+            Message::Frame { round: 0, frame: 3, moves: vec![Move::Up, Move::Left, Move::Left, Move::Hold, Move::Down] },
+            Message::Check { round: 1, frame: 40, hash: 0x00ff_1234_abcd_0001, garbage: 7 },
+            Message::Bye,
+        ];
+        for message in messages {
+            assert_eq!(Message::decode(&message.encode()), Ok(message));
+        }
+    }
+
+    #[test]
+    fn garbled_messages_are_refused() {
+        for line in ["", "hello tetris-broadcast 1", "match 1", "match 1 3", "match 1 3 diagonal", "frame 0 1", "frame 0 1 ?", "check 0 20 nothex 1",
+            "check 0 20 ff", "welcome"] {
+            assert!(Message::decode(line).is_err(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::modes::Mode;
+use crate::rules::Rules;
+use crate::save::{field_or, raw_field, read_board, read_fields};
+use crate::terminal::*;
+use crate::terminal::Color::*;
+use crate::{check_ctrl_c, ctrl_c_channel, input_channel, run_game};
+use crate::{Block, Game, BLOCKS, BOARD_HEIGHT, BOARD_WIDTH, DRAW_SPEED, SPAWN_ROWS};
+
+const PUZZLES_HEADER: &str = "tetris-puzzles";
+const PUZZLES_VERSION: u32 = 1;
+const CHOICE_PAUSE: Duration = Duration::from_millis(500);
+
+/// What a puzzle asks to do with its blocks.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Goal {
+    Lines(u32),
+    PerfectClear,
+    // a T turned into a slot, clearing two lines with it
+    TSpinDouble,
+}
+
+impl Goal {
+    fn parse(text: &str) -> Result<Goal, String> {
+        match text.split_whitespace().collect::<Vec<_>>()[..] {
+            ["lines", lines] => lines.parse().map(Goal::Lines).map_err(|_| format!("invalid goal: {}", text)),
+            ["perfect-clear"] => Ok(Goal::PerfectClear),
+            ["tspin-double"] => Ok(Goal::TSpinDouble),
+            _ => Err(format!("invalid goal: {}", text)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Goal::Lines(1) => "Clear a line".to_string(),
+            Goal::Lines(lines) => format!("Clear {} lines", lines),
+            Goal::PerfectClear => "Perfect clear".to_string(),
+            Goal::TSpinDouble => "T-spin double".to_string(),
+        }
+    }
+
+    pub fn reached(&self, game: &Game) -> bool {
+        match self {
+            Goal::Lines(lines) => game.lines_cleared >= *lines,
+            Goal::PerfectClear => game.last_clear.perfect,
+            Goal::TSpinDouble => game.last_clear.tspin && game.last_clear.lines == 2,
+        }
+    }
+}
+
+/// A board to start from, the blocks to play on it and what to achieve with them.
+pub struct Puzzle {
+    name: String,
+    board: [[u8; BOARD_HEIGHT]; BOARD_WIDTH],
+    blocks: Vec<Block>,
+    hold: bool,
+    goal: Goal,
+}
+
+/// Reads a pack of puzzles, a `tetris-puzzles <version>` line followed by the puzzles,
+/// each a group of `key value` lines set apart by an empty line:
+///
+/// ```text
+/// name Fill the well
+/// goal lines 4              (or perfect-clear, tspin-double)
+/// blocks I                  (I O T S Z J L, played in this order)
+/// hold 0                    (1 allows holding, off by default)
+/// board 8888808888/8888808888/8888808888/8888808888
+/// ```
+///
+/// The board rows are given like in saves, from the top down to the bottom of the board.
+pub fn load_pack(path: &Path) -> Result<Vec<Puzzle>, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("cannot read {}: {}", path.display(), error))?;
+    read_fields(&text, PUZZLES_HEADER, PUZZLES_VERSION)?;
+    let puzzles = text.split("\n\n")
+        .skip(1)
+        .filter(|group| !group.trim().is_empty())
+        .map(|group| read_puzzle(&group.lines().filter_map(|line| line.split_once(' ')).collect()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if puzzles.is_empty() {
+        return Err(format!("no puzzles in {}", path.display()));
+    }
+    Ok(puzzles)
+}
+
+fn read_puzzle(fields: &HashMap<&str, &str>) -> Result<Puzzle, String> {
+    let name = raw_field(fields, "name")?.to_string();
+    let puzzle = Puzzle {
+        board: read_board(raw_field(fields, "board")?).map_err(|error| format!("{}: {}", name, error))?,
+        blocks: raw_field(fields, "blocks")?.chars().map(read_block).collect::<Result<_, _>>()?,
+        hold: field_or::<u8>(fields, "hold", 0)? != 0,
+        goal: Goal::parse(raw_field(fields, "goal")?)?,
+        name,
+    };
+    if puzzle.blocks.is_empty() {
+        return Err(format!("{}: no blocks to play", puzzle.name));
+    }
+    if (0..BOARD_WIDTH).any(|x| (0..SPAWN_ROWS).any(|y| puzzle.board[x][y] != 0)) {
+        return Err(format!("{}: the board leaves no room for blocks to appear", puzzle.name));
+    }
+    Ok(puzzle)
+}
+
+// the letter every block is known by, after the shape it makes
+const LETTERS: [(char, Color); 7] =
+    [('I', GREEN), ('O', BLUE), ('S', RED), ('Z', YELLOW), ('L', MAGENTA), ('J', CYAN), ('T', WHITE)];
+
+pub fn read_block(letter: char) -> Result<Block, String> {
+    let (_, color) = LETTERS.iter().find(|(l, _)| *l == letter).ok_or_else(|| format!("unknown block {}", letter))?;
+    Ok(*BLOCKS.iter().find(|b| b.color as u8 == *color as u8).expect("every letter has a block"))
+}
+
+pub fn block_letter(block: &Block) -> char {
+    LETTERS.iter().find(|(_, color)| *color as u8 == block.color as u8).map(|(l, _)| *l).expect("every block has a letter")
+}
+
+impl Game {
+    pub fn from_puzzle(puzzle: &Puzzle, practice: bool) -> Game {
+        let rules = Rules {
+            mode: Mode::Puzzle { goal: puzzle.goal, hold: puzzle.hold },
+            practice,
+            ..Rules::default()
+        };
+        let mut game = Game::new(0, rules);
+        game.board = puzzle.board;
+        game.sequence = puzzle.blocks.clone();
+        game.block = game.deal();
+        game.next_block = game.deal();
+        game
+    }
+}
+
+/// Plays the puzzles of a pack one after the other, each can be retried or skipped once it's over.
+pub fn play_puzzles(pack: Vec<Puzzle>, practice: bool) {
+    let input_rx = input_channel(Some);
+    let ctrl_c_rx = ctrl_c_channel();
+    let mut solved = vec![false; pack.len()];
+    let mut index = 0;
+    while index < pack.len() {
+        let puzzle = &pack[index];
+        line_in_color(&format!("Puzzle {} of {}: {}", index + 1, pack.len(), puzzle.name), YELLOW);
+        create_game_screen(24);
+
+        let new_game = || Game::from_puzzle(puzzle, practice);
+        let mut game = new_game();
+        if !run_game(&mut game, false, &input_rx, &ctrl_c_rx, new_game, None) {
+            break;
+        }
+        solved[index] |= game.finished();
+        let verdict = match game.finished() {
+            true => blink!("  SOLVED  ", GREEN),
+            false => blink!("  FAILED  ", RED),
+        };
+        game.draw_with_messages(&[
+            (8, verdict),
+            (10, color!(" r retry  ", WHITE)),
+            (11, color!(" n next   ", WHITE)),
+            (12, color!(" q quit   ", WHITE)),
+        ]);
+
+        // keys still pressed for the last block shouldn't answer
+        sleep(CHOICE_PAUSE);
+        while input_rx.try_recv().is_ok() {}
+        match wait_for_choice(&input_rx, &ctrl_c_rx) {
+            Some(Choice::Retry) => {}
+            Some(Choice::Next) => index += 1,
+            None => break,
+        }
+    }
+    let count = solved.iter().filter(|solved| **solved).count();
+    line_in_color(&format!("Solved {} of {} puzzles", count, pack.len()), BLUE);
+}
+
+enum Choice {
+    Retry,
+    Next,
+}
+
+/// Waits for the player to retry or go on, None when they quit.
+fn wait_for_choice(input_rx: &Receiver<Key>, ctrl_c_rx: &Receiver<()>) -> Option<Choice> {
+    loop {
+        if check_ctrl_c(ctrl_c_rx) {
+            return None;
+        }
+        match input_rx.recv_timeout(Duration::from_millis(DRAW_SPEED)) {
+            Ok(Key::Char(b'r')) => return Some(Choice::Retry),
+            Ok(Key::Char(b'n')) => return Some(Choice::Next),
+            Ok(Key::Char(b'q')) => return None,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use rand::{Error, Rng, RngCore};
+
+/// Seed for a game when the player didn't pick one.
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen()
+}
+
+/// Small random generator (splitmix64) whose whole state is a single number,
+/// so a game in progress can be saved and restored exactly.
+#[derive(Copy, Clone)]
+pub struct Randomizer {
+    state: u64,
+}
+
+impl Randomizer {
+    pub fn new(seed: u64) -> Randomizer {
+        Randomizer { state: seed }
+    }
+
+    pub fn from_state(state: u64) -> Randomizer {
+        Randomizer { state }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// A generator drawing numbers of its own from the same seed, for draws that mustn't move
+    /// the ones this generator makes next. `stream` tells the forks apart.
+    pub fn fork(&self, stream: u64) -> Randomizer {
+        Randomizer { state: self.state ^ stream }
+    }
+}
+
+impl RngCore for Randomizer {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::save::data_dir;
+
+/// Personal bests, one `<mode-goal> <value>` per line in the records file.
+pub struct Records {
+    path: PathBuf,
+    entries: BTreeMap<String, u64>,
+}
+
+impl Records {
+    pub fn load() -> Records {
+        let path = data_dir().join("records");
+        let entries = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter_map(|(key, value)| Some((key.to_string(), value.trim().parse().ok()?)))
+            .collect();
+        Records { path, entries }
+    }
+
+    pub fn best(&self, key: &str) -> Option<u64> {
+        self.entries.get(key).copied()
+    }
+
+    /// Keeps the value when it beats the current best, returns whether it did.
+    pub fn submit(&mut self, key: &str, value: u64, lower_is_better: bool) -> Result<bool, String> {
+        let better = match self.best(key) {
+            Some(best) if lower_is_better => value < best,
+            Some(best) => value > best,
+            None => true,
+        };
+        if better {
+            self.entries.insert(key.to_string(), value);
+            self.save()?;
+        }
+        Ok(better)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+        }
+        let text: String = self.entries.iter().map(|(key, value)| format!("{} {}\n", key, value)).collect();
+        fs::write(&self.path, text).map_err(|e| format!("cannot write {}: {}", self.path.display(), e))
+    }
+}
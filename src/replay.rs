@@ -0,0 +1,317 @@
+use std::fs;
+use std::iter::{repeat_n, Peekable};
+use std::path::{Path, PathBuf};
+use std::slice::Iter;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::rules::Rules;
+use crate::save::{data_dir, field, raw_field, read_fields};
+use crate::terminal::*;
+use crate::terminal::Color::*;
+use crate::{ctrl_c_channel, check_ctrl_c, input_channel, Game, Move, DRAW_SPEED, TETRIS_PAUSE};
+
+use Control::*;
+
+const REPLAY_HEADER: &str = "tetris-replay";
+const REPLAY_VERSION: u32 = 1;
+
+// frames in a day of play, no replay runs longer so verifying one always ends
+const LONGEST_REPLAY: u64 = 24 * 60 * 60 * 1000 / DRAW_SPEED;
+
+const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 2;
+
+/// Everything needed to play a game again: the seed, the rules, each move with the frame it was made in
+/// and the time each frame took.
+pub struct Replay {
+    pub seed: u64,
+    pub rules: Rules,
+    pub inputs: Vec<(u64, Move)>,
+    // milliseconds of each frame once the countdown was over
+    pub timing: Vec<u64>,
+    // frame the recording stopped in, either by game over or by leaving the game
+    pub end_frame: u64,
+    // how the game ended according to whoever recorded it
+    pub result: Option<Outcome>,
+    // placements were undone while recording, the inputs only hold what was kept
+    pub assisted: bool,
+}
+
+#[derive(PartialEq)]
+pub struct Outcome {
+    pub score: u32,
+    pub lines: u32,
+    pub level: usize,
+    pub pieces: u32,
+    pub millis: u64,
+    pub hash: u64,
+}
+
+impl Outcome {
+    pub fn of(game: &Game) -> Outcome {
+        Outcome {
+            score: game.score,
+            lines: game.lines_cleared,
+            level: game.level + 1,
+            pieces: game.pieces,
+            millis: game.time_millis(),
+            hash: game.state_hash(),
+        }
+    }
+
+    fn encode(&self) -> String {
+        format!("{} {} {} {} {} {:016x}", self.score, self.lines, self.level, self.pieces, self.millis, self.hash)
+    }
+
+    fn decode(text: &str) -> Result<Outcome, String> {
+        let values: Vec<&str> = text.split_whitespace().collect();
+        let invalid = || format!("invalid result: {}", text);
+        if values.len() != 6 {
+            return Err(invalid());
+        }
+        Ok(Outcome {
+            score: values[0].parse().map_err(|_| invalid())?,
+            lines: values[1].parse().map_err(|_| invalid())?,
+            level: values[2].parse().map_err(|_| invalid())?,
+            pieces: values[3].parse().map_err(|_| invalid())?,
+            millis: values[4].parse().map_err(|_| invalid())?,
+            hash: u64::from_str_radix(values[5], 16).map_err(|_| invalid())?,
+        })
+    }
+}
+
+pub enum Frame {
+    Played,
+    GameOver,
+    Ended,
+}
+
+enum Control {
+    Faster,
+    Slower,
+    Pause,
+    Step,
+    Quit,
+}
+
+impl Replay {
+    pub fn of(game: &Game) -> Replay {
+        Replay {
+            seed: game.seed,
+            rules: game.rules,
+            inputs: game.inputs.clone(),
+            timing: game.timing.clone(),
+            end_frame: game.frame,
+            result: Some(Outcome::of(game)),
+            assisted: game.assisted,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut lines = vec![
+            format!("{} {}", REPLAY_HEADER, REPLAY_VERSION),
+            format!("seed {}", self.seed),
+        ];
+        lines.extend(self.rules.fields());
+        lines.push(format!("inputs {}", encode_inputs(&self.inputs)));
+        lines.push(format!("timing {}", encode_timing(&self.timing)));
+        lines.push(format!("end_frame {}", self.end_frame));
+        lines.push(format!("assisted {}", self.assisted as u8));
+        if let Some(result) = &self.result {
+            lines.push(format!("result {}", result.encode()));
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    pub fn decode(text: &str) -> Result<Replay, String> {
+        let (_, fields) = read_fields(text, REPLAY_HEADER, REPLAY_VERSION)?;
+        let inputs = decode_inputs(raw_field(&fields, "inputs")?)?;
+        let end_frame = field(&fields, "end_frame")?;
+        if end_frame > LONGEST_REPLAY {
+            return Err(format!("end_frame {} is past a day of play", end_frame));
+        }
+        Ok(Replay {
+            seed: field(&fields, "seed")?,
+            rules: Rules::from_fields(&fields)?,
+            timing: decode_timing(raw_field(&fields, "timing")?, end_frame)?,
+            end_frame,
+            result: fields.get("result").map(|result| Outcome::decode(result)).transpose()?,
+            assisted: field::<u8>(&fields, "assisted")? != 0,
+            inputs,
+        })
+    }
+
+    /// A fresh game set up exactly like the recorded one, ready to be fed the inputs.
+    pub fn new_game(&self) -> Game {
+        let mut game = Game::new(self.seed, self.rules);
+        game.assisted = self.assisted;
+        game
+    }
+
+    /// Plays the next frame of the recording, feeding in the moves made in it.
+    pub fn play_frame(&self, game: &mut Game, inputs: &mut Peekable<Iter<(u64, Move)>>) -> Frame {
+        if game.frame >= self.end_frame {
+            return Frame::Ended;
+        }
+        // frames a replay has no timing for take as long as they should
+        let millis = self.timing.get(game.clock as usize).copied().unwrap_or(DRAW_SPEED);
+        if !game.timed_tick(millis) {
+            return Frame::GameOver;
+        }
+        while let Some((_, mov)) = inputs.next_if(|(frame, _)| *frame == game.frame) {
+            game.apply(*mov);
+        }
+        Frame::Played
+    }
+}
+
+/// Moves as `<frames since previous move><move code>`, e.g. `12r3r0u`.
+pub fn encode_inputs(inputs: &[(u64, Move)]) -> String {
+    let mut previous = 0;
+    let mut text = String::new();
+    for (frame, mov) in inputs {
+        text.push_str(&(frame - previous).to_string());
+        text.push(mov.code());
+        previous = *frame;
+    }
+    text
+}
+
+pub fn decode_inputs(text: &str) -> Result<Vec<(u64, Move)>, String> {
+    let mut inputs = Vec::new();
+    let mut frame = 0;
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let delta: u64 = digits.parse().map_err(|_| format!("invalid inputs near {}", c))?;
+        let mov = Move::from_code(c).ok_or_else(|| format!("invalid move {}", c))?;
+        frame += delta;
+        inputs.push((frame, mov));
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return Err("inputs end without a move".to_string());
+    }
+    Ok(inputs)
+}
+
+/// Milliseconds of each frame, a run of frames that took as long written once with a count,
+/// e.g. `50*12,51,49*2`.
+pub fn encode_timing(timing: &[u64]) -> String {
+    let mut runs: Vec<(u64, usize)> = Vec::new();
+    for &millis in timing {
+        match runs.last_mut() {
+            Some((last, count)) if *last == millis => *count += 1,
+            _ => runs.push((millis, 1)),
+        }
+    }
+    runs.iter()
+        .map(|(millis, count)| match count {
+            1 => millis.to_string(),
+            _ => format!("{}*{}", millis, count),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Reads the timing of at most `frames` frames.
+pub fn decode_timing(text: &str, frames: u64) -> Result<Vec<u64>, String> {
+    let mut timing = Vec::new();
+    for run in text.split(',').filter(|run| !run.trim().is_empty()) {
+        let invalid = || format!("invalid timing: {}", run);
+        let (millis, count) = run.trim().split_once('*').unwrap_or((run.trim(), "1"));
+        let millis: u64 = millis.parse().map_err(|_| invalid())?;
+        let count: u64 = count.parse().map_err(|_| invalid())?;
+        if timing.len() as u64 + count > frames {
+            return Err(format!("timing of more than {} frames", frames));
+        }
+        timing.extend(repeat_n(millis, count as usize));
+    }
+    Ok(timing)
+}
+
+/// Stores the replay of a finished game with the other replays, returns where it went.
+pub fn save_replay(game: &Game) -> Result<PathBuf, String> {
+    if game.frame > LONGEST_REPLAY {
+        return Err("the game ran longer than a replay can".to_string());
+    }
+    let dir = data_dir().join("replays");
+    fs::create_dir_all(&dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let path = dir.join(format!("{}-{}.replay", now, game.seed));
+    fs::write(&path, Replay::of(game).encode()).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+pub fn load_replay(path: &Path) -> Result<Replay, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    Replay::decode(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Shows the replay on screen, the viewer can change speed, pause and step through it frame by frame.
+pub fn play_replay(replay: Replay) {
+    line_in_color("Replay: a/d slower/faster, p pause, s step, q quit\n", YELLOW);
+    create_game_screen(24);
+
+    let mut game = replay.new_game();
+    let mut inputs = replay.inputs.iter().peekable();
+    let control_rx = input_channel(control_for_key);
+    let ctrl_c_rx = ctrl_c_channel();
+
+    let mut speed = NORMAL_SPEED;
+    let mut paused = false;
+    let game_over = loop {
+        let mut step = !paused;
+        while let Ok(control) = control_rx.try_recv() {
+            match control {
+                Faster => speed = (speed + 1).min(SPEEDS.len() - 1),
+                Slower => speed = speed.saturating_sub(1),
+                Pause => paused = !paused,
+                Step => step = paused,
+                Quit => return,
+            }
+        }
+        if check_ctrl_c(&ctrl_c_rx) {
+            return;
+        }
+
+        let frame_time = Duration::from_millis(DRAW_SPEED).div_f64(SPEEDS[speed]);
+        if step {
+            match replay.play_frame(&mut game, &mut inputs) {
+                Frame::Played => {}
+                Frame::GameOver => break true,
+                Frame::Ended => break false,
+            }
+            if game.take_tetris() {
+                game.tetris(TETRIS_PAUSE.div_f64(SPEEDS[speed]));
+            }
+        }
+        game.draw();
+        line_in_color(&format!("{:>5}x{}  frame {}", SPEEDS[speed], if paused { "  paused" } else { "" }, game.frame), BLUE);
+        sleep(frame_time);
+    };
+    if game_over && game.finished() {
+        game.draw_with_messages(&[(10, blink!(" FINISHED ", GREEN))]);
+    } else if game_over {
+        game.game_over();
+    } else {
+        game.draw();
+        line_in_color("End of replay", BLUE);
+    }
+}
+
+fn control_for_key(key: Key) -> Option<Control> {
+    match key {
+        Key::Char(b'd') | Key::Right => Some(Faster),
+        Key::Char(b'a') | Key::Left => Some(Slower),
+        Key::Char(b'p') | Key::Char(b' ') => Some(Pause),
+        Key::Char(b's') | Key::Down => Some(Step),
+        Key::Char(b'q') => Some(Quit),
+        _ => None,
+    }
+}
@@ -0,0 +1,491 @@
+use std::cmp::Reverse;
+use std::io::Write;
+use std::mem;
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::thread::{sleep, JoinHandle};
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::garbage::Holes;
+use crate::modes::Mode;
+use crate::pacer::Pacer;
+use crate::protocol::{listen, spawn_writer};
+use crate::rules::Rules;
+use crate::server::{key_channel, CTRL_C, NEGOTIATION};
+use crate::terminal::*;
+use crate::terminal::Color::*;
+use crate::{check_ctrl_c, ctrl_c_channel, input_channel, move_for_key, random_seed, Game, DRAW_SPEED};
+
+pub const MIN_PLAYERS: usize = 3;
+pub const MAX_PLAYERS: usize = 99;
+// frames a random target is kept before picking another
+const RANDOM_TARGET_FRAMES: u64 = 3000 / DRAW_SPEED;
+// frames between redrawing the standings on the server
+const STANDINGS_FRAMES: u64 = 500 / DRAW_SPEED;
+const LOBBY_WAIT: Duration = Duration::from_millis(100);
+const RESULTS_PAUSE: Duration = Duration::from_millis(5000);
+// a player whose connection takes longer than this to take a frame is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+// lines of a player's screen: the board with the frame around it, then what's going on in the match
+const BOARD_LINES: usize = 22;
+const SCREEN_LINES: u8 = BOARD_LINES as u8 + 4;
+
+/// Who a player's attacks go to.
+#[derive(Copy, Clone, PartialEq)]
+enum Strategy {
+    Random,
+    // everyone targeting the player, at once
+    Attackers,
+    // the opponent with the most badges
+    Badges,
+    // the opponent closest to topping out
+    KOs,
+}
+
+const STRATEGIES: [Strategy; 4] = [Strategy::Random, Strategy::Attackers, Strategy::Badges, Strategy::KOs];
+
+impl Strategy {
+    fn name(&self) -> &str {
+        match self {
+            Strategy::Random => "random",
+            Strategy::Attackers => "attackers",
+            Strategy::Badges => "badges",
+            Strategy::KOs => "KOs",
+        }
+    }
+}
+
+/// Badges are won by knocking players out, with the badges they had, and make attacks stronger.
+fn boost(badges: u32) -> u32 {
+    match badges {
+        0..=1 => 0,
+        2..=5 => 25,
+        6..=13 => 50,
+        14..=29 => 75,
+        _ => 100,
+    }
+}
+
+/// Rows of the stack plus the garbage waiting to rise under it.
+fn danger(game: &Game) -> usize {
+    let top = (0..game.height()).find(|&y| (0..game.width()).any(|x| game.board[x][y] != 0)).unwrap_or(game.height());
+    game.height() - top + game.incoming_rows() as usize
+}
+
+struct Player {
+    name: String,
+    // screens go out on a thread of the player's own, so one who stops reading holds no one up
+    screens: Sender<Vec<u8>>,
+    writer: JoinHandle<()>,
+    keys: Receiver<Key>,
+    game: Game,
+    strategy: Strategy,
+    target: Option<usize>,
+    // frame the random strategy picks another target
+    retarget: u64,
+    // the last to send garbage, who gets the KO
+    attacker: Option<usize>,
+    kos: u32,
+    badges: u32,
+    sent: u32,
+    // set once knocked out, 1 for the winner, 0 while waiting for a match to play in
+    place: Option<usize>,
+    standings: bool,
+    connected: bool,
+}
+
+impl Player {
+    fn alive(&self) -> bool {
+        self.place.is_none()
+    }
+
+    /// Writes a screen over the one drawn before, a player the screen can't reach is gone.
+    fn show(&mut self, lines: &[String]) {
+        let mut screen = Vec::new();
+        restore_cursor_to(&mut screen).ok();
+        for line in lines {
+            overwrite_to(&mut screen).ok();
+            write!(screen, "{}\r\n", line).ok();
+        }
+        self.send(&screen);
+    }
+
+    fn send(&mut self, bytes: &[u8]) {
+        if self.connected && self.screens.send(bytes.to_vec()).is_err() {
+            self.connected = false;
+        }
+    }
+}
+
+/// Players connected to the server, playing matches where everyone attacks everyone until one is left.
+pub struct Royale {
+    port: u16,
+    holes: Holes,
+    joined: Receiver<TcpStream>,
+    players: Vec<Player>,
+}
+
+impl Royale {
+    pub fn open(port: u16, holes: Holes) -> Result<Royale, String> {
+        let listener = listen(port)?;
+        let (joined_tx, joined) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                if joined_tx.send(stream).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Royale { port, holes, joined, players: Vec::new() })
+    }
+
+    /// Runs matches with whoever is connected, each started from the server's terminal,
+    /// until the server quits.
+    pub fn run(mut self) {
+        let input_rx = input_channel(Some);
+        let ctrl_c_rx = ctrl_c_channel();
+        while self.lobby(&input_rx, &ctrl_c_rx) {
+            if !self.play_match(&input_rx, &ctrl_c_rx) {
+                break;
+            }
+            sleep(RESULTS_PAUSE);
+        }
+        for mut player in self.players {
+            let mut bye = Vec::new();
+            cursor_visible_to(&mut bye, true).ok();
+            line_in_color_to(&mut bye, "The server closed, thanks for playing!", YELLOW).ok();
+            player.send(&bye);
+            // the goodbye goes out before the server does
+            drop(player.screens);
+            player.writer.join().ok();
+        }
+    }
+
+    /// Takes in players until the server starts a match with enter, false when it quits.
+    fn lobby(&mut self, input_rx: &Receiver<Key>, ctrl_c_rx: &Receiver<()>) -> bool {
+        self.players.retain(|player| player.connected);
+        let mut shown = None;
+        loop {
+            self.take_joined(false);
+            for player in &mut self.players {
+                loop {
+                    match player.keys.try_recv() {
+                        Ok(Key::Char(CTRL_C | b'q')) | Err(TryRecvError::Disconnected) => player.connected = false,
+                        Ok(_) => continue,
+                        Err(TryRecvError::Empty) => {}
+                    }
+                    break;
+                }
+            }
+            self.players.retain(|player| player.connected);
+
+            let count = self.players.len();
+            if shown != Some(count) {
+                shown = Some(count);
+                clear_screen();
+                line_in_color(&format!("Battle royale on port {}, join with telnet <host> {}", self.port, self.port), YELLOW);
+                match count >= MIN_PLAYERS {
+                    true => line_in_color(&format!("{} players in, enter starts the match, q quits", count), WHITE),
+                    false => line_in_color(&format!("{} players in, {} needed to start, q quits", count, MIN_PLAYERS), WHITE),
+                }
+                for player in &self.players {
+                    line_in_color(&player.name, BLUE);
+                }
+                let mut waiting = vec![color!(format!("{} players in, waiting for the match to start", count), WHITE)];
+                waiting.resize(SCREEN_LINES as usize, String::new());
+                for player in &mut self.players {
+                    player.show(&waiting);
+                }
+            }
+
+            if check_ctrl_c(ctrl_c_rx) {
+                return false;
+            }
+            match input_rx.recv_timeout(LOBBY_WAIT) {
+                Ok(Key::Char(b'\r' | b'\n')) if count >= MIN_PLAYERS => return true,
+                Ok(Key::Char(b'q')) => return false,
+                _ => {}
+            }
+        }
+    }
+
+    /// Greets the players who connected, those joining during a match wait for the next one.
+    fn take_joined(&mut self, playing: bool) {
+        while let Ok(mut stream) = self.joined.try_recv() {
+            if self.players.len() >= MAX_PLAYERS {
+                let mut full = Vec::new();
+                line_in_color_to(&mut full, "The server is full, try again later", RED).ok();
+                stream.write_all(&full).ok();
+                continue;
+            }
+            let Ok(keys) = stream.try_clone().map(key_channel) else {
+                continue;
+            };
+            stream.set_write_timeout(Some(WRITE_TIMEOUT)).ok();
+            let (screens, writer) = spawn_writer(stream);
+            let number = (1..).find(|n| self.players.iter().all(|p| p.name != format!("Player {}", n))).expect("numbers are endless");
+            let mut player = Player {
+                name: format!("Player {}", number),
+                screens,
+                writer,
+                keys,
+                game: Game::new(0, Rules::default()),
+                strategy: Strategy::Random,
+                target: None,
+                retarget: 0,
+                attacker: None,
+                kos: 0,
+                badges: 0,
+                sent: 0,
+                place: Some(0),
+                standings: false,
+                connected: true,
+            };
+            let mut screen = NEGOTIATION.to_vec();
+            clear_screen_to(&mut screen).ok();
+            cursor_visible_to(&mut screen, false).ok();
+            line_in_color_to(&mut screen, &format!("Battle royale, you are {}", player.name), YELLOW).ok();
+            create_game_screen_to(&mut screen, SCREEN_LINES).ok();
+            if playing {
+                line_in_color_to(&mut screen, "A match is on, you play in the next one", WHITE).ok();
+            }
+            player.send(&screen);
+            self.players.push(player);
+        }
+    }
+
+    /// Plays a match until one player is left, false when the server quit during it.
+    fn play_match(&mut self, input_rx: &Receiver<Key>, ctrl_c_rx: &Receiver<()>) -> bool {
+        let seed = random_seed();
+        let rules = Rules { mode: Mode::Versus, holes: self.holes, ..Rules::default() };
+        for player in &mut self.players {
+            // everyone gets the same blocks
+            player.game = Game::new(seed, rules);
+            player.strategy = Strategy::Random;
+            player.target = None;
+            player.attacker = None;
+            (player.kos, player.badges, player.sent) = (0, 0, 0);
+            player.place = None;
+            player.standings = false;
+        }
+        let playing = self.players.len();
+
+        let mut pacer = Pacer::start();
+        let mut frame = 0;
+        loop {
+            if check_ctrl_c(ctrl_c_rx) || input_rx.try_iter().any(|key| matches!(key, Key::Char(b'q'))) {
+                return false;
+            }
+            self.take_joined(true);
+            self.read_keys(playing);
+            let toppled = self.play_frame(playing);
+            self.knock_out(&toppled, playing);
+            self.pick_targets(frame, playing);
+
+            let standings = self.standings();
+            for index in 0..playing {
+                let screen = self.screen(index, playing, &standings);
+                self.players[index].show(&screen);
+            }
+            if frame % STANDINGS_FRAMES == 0 {
+                self.draw_standings(&standings);
+            }
+            if self.players[..playing].iter().filter(|player| player.alive()).count() <= 1 {
+                if let Some(winner) = self.players[..playing].iter_mut().find(|player| player.alive()) {
+                    winner.place = Some(1);
+                }
+                let standings = self.standings();
+                self.draw_standings(&standings);
+                for index in 0..playing {
+                    let screen = self.screen(index, playing, &standings);
+                    self.players[index].show(&screen);
+                }
+                return true;
+            }
+
+            frame += 1;
+            pacer.wait();
+        }
+    }
+
+    /// Plays the keys pressed since the last frame, a player who leaves is knocked out.
+    fn read_keys(&mut self, playing: usize) {
+        for player in &mut self.players[..playing] {
+            loop {
+                let key = match player.keys.try_recv() {
+                    Ok(key) => key,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        player.connected = false;
+                        break;
+                    }
+                };
+                match key {
+                    Key::Char(CTRL_C | b'q') => player.connected = false,
+                    Key::Char(b'v') => player.standings = !player.standings,
+                    Key::Char(digit @ b'1'..=b'4') => player.strategy = STRATEGIES[(digit - b'1') as usize],
+                    key => if let Some(mov) = move_for_key(key) {
+                        player.game.apply(mov);
+                    },
+                }
+            }
+        }
+    }
+
+    /// Plays a frame of every game still on and sends the garbage of the lines cleared,
+    /// returns the players who topped out.
+    fn play_frame(&mut self, playing: usize) -> Vec<usize> {
+        let mut toppled = Vec::new();
+        for (index, player) in self.players[..playing].iter_mut().enumerate() {
+            if player.alive() && (!player.connected || !player.game.tick()) {
+                toppled.push(index);
+            }
+        }
+        let attackers = self.attackers(playing);
+        for index in 0..playing {
+            let player = &mut self.players[index];
+            let lines = mem::take(&mut player.game.outgoing);
+            if lines == 0 || !player.alive() {
+                continue;
+            }
+            let lines = lines * (100 + boost(player.badges)) / 100;
+            player.sent += lines;
+            let targets = match (player.strategy, &attackers[index]) {
+                (Strategy::Attackers, attackers) if !attackers.is_empty() => attackers.clone(),
+                _ => player.target.into_iter().collect(),
+            };
+            for target in targets {
+                self.players[target].game.receive_garbage(lines);
+                self.players[target].attacker = Some(index);
+            }
+        }
+        toppled
+    }
+
+    /// The players targeting each player.
+    fn attackers(&self, playing: usize) -> Vec<Vec<usize>> {
+        let mut attackers = vec![Vec::new(); playing];
+        for (index, player) in self.players[..playing].iter().enumerate() {
+            if let (true, Some(target)) = (player.alive(), player.target) {
+                attackers[target].push(index);
+            }
+        }
+        attackers
+    }
+
+    /// Takes the players who topped out out of the match, the last to attack them gets the KO and their badges.
+    fn knock_out(&mut self, toppled: &[usize], playing: usize) {
+        let alive = self.players[..playing].iter().filter(|player| player.alive()).count();
+        for &index in toppled {
+            // players toppling in the same frame share the place
+            self.players[index].place = Some(alive - toppled.len() + 1);
+            let badges = self.players[index].badges;
+            if let Some(attacker) = self.players[index].attacker.filter(|&attacker| self.players[attacker].alive()) {
+                self.players[attacker].kos += 1;
+                self.players[attacker].badges += 1 + badges;
+            }
+        }
+    }
+
+    fn pick_targets(&mut self, frame: u64, playing: usize) {
+        let alive = (0..playing).filter(|&index| self.players[index].alive()).collect::<Vec<_>>();
+        let attackers = self.attackers(playing);
+        let mut rng = rand::thread_rng();
+        for &index in &alive {
+            let others = alive.iter().copied().filter(|&other| other != index).collect::<Vec<_>>();
+            if others.is_empty() {
+                continue;
+            }
+            let player = &self.players[index];
+            let current = player.target.filter(|target| others.contains(target));
+            let random = others[rng.gen_range(0..others.len())];
+            let target = match player.strategy {
+                Strategy::Random => match current {
+                    Some(target) if frame < player.retarget => target,
+                    _ => {
+                        self.players[index].retarget = frame + RANDOM_TARGET_FRAMES;
+                        random
+                    }
+                },
+                Strategy::Attackers => match &attackers[index][..] {
+                    [] => current.unwrap_or(random),
+                    attackers => attackers[rng.gen_range(0..attackers.len())],
+                },
+                Strategy::Badges => *others.iter().max_by_key(|&&other| self.players[other].badges).expect("others are there"),
+                Strategy::KOs => *others.iter().max_by_key(|&&other| danger(&self.players[other].game)).expect("others are there"),
+            };
+            self.players[index].target = Some(target);
+        }
+    }
+
+    /// Every player, the ones still in first, then by how they placed.
+    fn standings(&self) -> Vec<String> {
+        let mut order = (0..self.players.len()).filter(|&index| self.players[index].place != Some(0)).collect::<Vec<_>>();
+        order.sort_by_key(|&index| {
+            let player = &self.players[index];
+            (player.place.unwrap_or(0), Reverse(player.kos), Reverse(player.sent))
+        });
+        let mut lines = vec![color!("Place  Player        KOs  Badges  Sent  Lines", BLUE)];
+        for index in order {
+            let player = &self.players[index];
+            let place = player.place.map_or("in".to_string(), |place| place.to_string());
+            let line = format!("{:>5}  {:<12} {:>4} {:>7} {:>5} {:>6}",
+                               place, player.name, player.kos, player.badges, player.sent, player.game.lines_cleared);
+            lines.push(match (player.place, player.connected) {
+                (Some(1), _) => color!(line, YELLOW),
+                (None, _) => line,
+                (_, true) => color!(line, BLACK),
+                (_, false) => color!(format!("{} left", line), BLACK),
+            });
+        }
+        lines
+    }
+
+    fn draw_standings(&self, standings: &[String]) {
+        clear_screen();
+        line_in_color(&format!("Battle royale on port {}, q ends the match and quits", self.port), YELLOW);
+        for line in standings {
+            overwrite();
+            println!("{}", line);
+        }
+    }
+
+    /// A player's board or the standings, with where they are in the match under it.
+    fn screen(&self, index: usize, playing: usize, standings: &[String]) -> Vec<String> {
+        let player = &self.players[index];
+        let mut lines = match (player.standings, player.place) {
+            (false, None) => player.game.screen(false, false, &[]),
+            (false, Some(1)) => player.game.screen(false, true, &[(8, blink!("  WINNER  ", GREEN))]),
+            (false, Some(place)) => player.game.screen(false, true, &[
+                (8, blink!("   K.O.   ", RED)),
+                (10, color!(format!("{:^1$}", format!("place {}", place), 10), WHITE)),
+            ]),
+            (true, _) => standings.iter().take(BOARD_LINES).cloned().collect(),
+        };
+        lines.resize(BOARD_LINES, String::new());
+
+        let alive = self.players[..playing].iter().filter(|player| player.alive()).count();
+        let attacked = self.players[..playing].iter().filter(|other| other.alive() && other.target == Some(index)).count();
+        lines.push(match player.place {
+            None => format!("In with {} of {}   KOs {}   Badges {} (+{}%)", alive, playing, player.kos, player.badges, boost(player.badges)),
+            Some(place) => format!("Place {} of {}   KOs {}   Badges {}", place, playing, player.kos, player.badges),
+        });
+        lines.push(match player.target.filter(|_| player.alive()) {
+            Some(target) => format!("Target {}, attacked by {}", self.players[target].name, attacked),
+            None => String::new(),
+        });
+        let strategies = STRATEGIES.iter().enumerate()
+            .map(|(number, strategy)| match *strategy == player.strategy {
+                true => inverted!(format!("{} {}", number + 1, strategy.name()), BLUE),
+                false => format!("{} {}", number + 1, strategy.name()),
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        lines.push(format!("Attack {}", strategies));
+        lines.push(color!("v standings or board, q leave", WHITE));
+        lines
+    }
+}
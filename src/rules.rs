@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use crate::garbage::Holes;
+use crate::modes::Mode;
+use crate::save::{field, raw_field};
+use crate::{DRAW_SPEED, SPAWN_DELAY};
+
+/// Settings that change how a game plays, kept with saves and replays so they play back the same.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Rules {
+    pub mode: Mode,
+    // frames between cementing a block and spawning the next one
+    pub spawn_delay: u64,
+    // placements can be undone and redone
+    pub practice: bool,
+    // placed cells disappear from view after `fade` frames,
+    // the stack's outline shows for `outline` frames after a line clear
+    pub invisible: bool,
+    pub fade: u64,
+    pub outline: u64,
+    // every cell of a block takes 2x2 on a board of half the width and height
+    pub big: bool,
+    // finesse faults show in the HUD
+    pub finesse: bool,
+    // where the holes of garbage from opponents go
+    pub holes: Holes,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            mode: Mode::Marathon { goal: None },
+            spawn_delay: SPAWN_DELAY,
+            practice: false,
+            invisible: false,
+            fade: 0,
+            outline: 0,
+            big: false,
+            finesse: false,
+            holes: Holes::default(),
+        }
+    }
+}
+
+impl Rules {
+    /// Name under which personal bests are kept, big blocks and a stack out of view keep records of their own.
+    pub fn record_key(&self) -> Option<String> {
+        let mut key = self.mode.record_key()?;
+        if self.big {
+            key.push_str("-big");
+        }
+        if self.invisible {
+            match self.fade {
+                0 => key.push_str("-invisible"),
+                fade => key.push_str(&format!("-fade{}", fade * DRAW_SPEED)),
+            }
+            if self.outline > 0 {
+                key.push_str(&format!("-outline{}", self.outline * DRAW_SPEED));
+            }
+        }
+        Some(key)
+    }
+
+    pub fn fields(&self) -> Vec<String> {
+        vec![
+            format!("mode {}", self.mode.encode()),
+            format!("spawn_delay {}", self.spawn_delay),
+            format!("practice {}", self.practice as u8),
+            format!("invisible {}", self.invisible as u8),
+            format!("fade {}", self.fade),
+            format!("outline {}", self.outline),
+            format!("big {}", self.big as u8),
+            format!("finesse {}", self.finesse as u8),
+            format!("holes {}", self.holes.encode()),
+        ]
+    }
+
+    pub fn from_fields(fields: &HashMap<&str, &str>) -> Result<Rules, String> {
+        Ok(Rules {
+            mode: Mode::decode(raw_field(fields, "mode")?)?,
+            spawn_delay: field(fields, "spawn_delay")?,
+            practice: field::<u8>(fields, "practice")? != 0,
+            invisible: field::<u8>(fields, "invisible")? != 0,
+            fade: field(fields, "fade")?,
+            outline: field(fields, "outline")?,
+            big: field::<u8>(fields, "big")? != 0,
+            finesse: field::<u8>(fields, "finesse")? != 0,
+            holes: Holes::parse(raw_field(fields, "holes")?)?,
+        })
+    }
+}
@@ -0,0 +1,169 @@
+use std::io;
+use std::io::Write;
+use std::sync::mpsc::Receiver;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::garbage::GARBAGE;
+use crate::modes::Mode;
+use crate::puzzle::{block_letter, read_block};
+use crate::rules::Rules;
+use crate::terminal::*;
+use crate::terminal::Color::*;
+use crate::{check_ctrl_c, ctrl_c_channel, input_channel, run_game};
+use crate::{Coord, Game, BLOCKS, BOARD_HEIGHT, BOARD_WIDTH, DRAW_SPEED};
+
+// where the board sits on the cleared screen, counted from 1 like mouse reports
+const BOARD_ROW: usize = 3;
+const BOARD_COLUMN: usize = 9;
+const HELP_ROW: usize = BOARD_ROW + BOARD_HEIGHT + 1;
+const HELP_LINES: u8 = 5;
+const GAME_OVER_PAUSE: Duration = Duration::from_millis(1000);
+// colors to paint with, garbage first as stacks are mostly practised on it
+const PAINTS: [u8; 8] =
+    [GARBAGE, GREEN as u8, BLUE as u8, RED as u8, YELLOW as u8, MAGENTA as u8, CYAN as u8, WHITE as u8];
+
+/// The position being set up, played from as often as wanted.
+struct Editor {
+    // board, hold block and blocks to come, the game is never played itself
+    setup: Game,
+    cursor: Coord,
+    paint: usize,
+}
+
+impl Editor {
+    /// A game from the position, the queued blocks come first and random ones after them.
+    fn start(&self) -> Game {
+        let mut game = Game::new(self.setup.seed, self.setup.rules);
+        game.board = self.setup.board;
+        game.hold_block = self.setup.hold_block;
+        game.sequence = self.setup.sequence.clone();
+        let block = game.deal();
+        game.block = game.centered(block);
+        game.next_block = game.deal();
+        game
+    }
+
+    fn draw(&self) {
+        // the block to play first shows as the next one, nothing falls while editing
+        let mut preview = self.start();
+        preview.next_block = Game::spawn_form(preview.block);
+        preview.spawn_delay = 1;
+        preview.draw();
+
+        let scale = self.setup.scale();
+        for row in 0..scale {
+            for column in 0..scale {
+                move_cursor_to(BOARD_ROW + self.cursor.y * scale + row, BOARD_COLUMN + self.cursor.x * scale + column);
+                // garbage is drawn in gray, the color of black cells
+                let color = if PAINTS[self.paint] == GARBAGE { BLACK as u8 } else { PAINTS[self.paint] };
+                print!("{}", blink!("◆", color));
+            }
+        }
+
+        move_cursor_to(HELP_ROW, 1);
+        let queue = self.setup.sequence.iter().map(block_letter).collect::<String>();
+        line_in_color(&format!("Queue {} then random", queue), BLUE);
+        line_in_color("arrows move, space paint or erase, c color, x clear board, mouse paints and erases", WHITE);
+        line_in_color("I O T S Z J L queue a block, backspace drops the last one, h hold block", WHITE);
+        line_in_color("e or enter plays from here and e in the game edits again, q quit", WHITE);
+        io::stdout().flush().expect("Cannot flush stdout");
+    }
+
+    /// Changes the position for a key, returns false when the player chose to quit.
+    fn edit(&mut self, key: Key) -> bool {
+        let (width, height) = (self.setup.width(), self.setup.height());
+        let cursor = &mut self.cursor;
+        match key {
+            Key::Up | Key::Char(b'w') => cursor.y = cursor.y.saturating_sub(1),
+            Key::Down | Key::Char(b's') => cursor.y = (cursor.y + 1).min(height - 1),
+            Key::Left | Key::Char(b'a') => cursor.x = cursor.x.saturating_sub(1),
+            Key::Right | Key::Char(b'd') => cursor.x = (cursor.x + 1).min(width - 1),
+            Key::Char(b' ') => {
+                let cell = &mut self.setup.board[cursor.x][cursor.y];
+                *cell = if *cell == 0 { PAINTS[self.paint] } else { 0 };
+            }
+            Key::Char(b'c') => self.paint = (self.paint + 1) % PAINTS.len(),
+            Key::Char(b'x') => self.setup.board = [[0; BOARD_HEIGHT]; BOARD_WIDTH],
+            Key::Char(b'h') => {
+                // cycles through the blocks in their usual order and back to no block held
+                let next = match self.setup.hold_block {
+                    None => 0,
+                    Some(block) => BLOCKS.iter().position(|b| b.color as u8 == block.color as u8)
+                        .expect("held blocks are known") + 1,
+                };
+                self.setup.hold_block = BLOCKS.get(next).copied();
+            }
+            Key::Char(0x7f | 0x08) => {
+                self.setup.sequence.pop();
+            }
+            Key::Char(letter) if letter.is_ascii_uppercase() => {
+                if let Ok(block) = read_block(letter as char) {
+                    self.setup.sequence.push(block);
+                }
+            }
+            Key::Mouse { button, column, row } if column >= BOARD_COLUMN && row >= BOARD_ROW => {
+                let scale = self.setup.scale();
+                let (x, y) = ((column - BOARD_COLUMN) / scale, (row - BOARD_ROW) / scale);
+                if x < width && y < height {
+                    *cursor = Coord { x, y };
+                    match button {
+                        0 => self.setup.board[x][y] = PAINTS[self.paint],
+                        2 => self.setup.board[x][y] = 0,
+                        _ => {}
+                    }
+                }
+            }
+            Key::Char(b'q') => return false,
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Edits a position and plays from it, going back and forth until the player quits.
+pub fn play_sandbox(seed: u64, rules: Rules) {
+    let input_rx = input_channel(Some);
+    let ctrl_c_rx = ctrl_c_channel();
+    let mut editor = Editor {
+        setup: Game::new(seed, Rules { mode: Mode::Sandbox, ..rules }),
+        cursor: Coord { x: 0, y: 0 },
+        paint: 0,
+    };
+    editor.cursor.y = editor.setup.height() - 1;
+
+    clear_screen();
+    mouse_reporting(true);
+    line_in_color("Sandbox", YELLOW);
+    create_game_screen(24 + HELP_LINES);
+    while edit_position(&mut editor, &input_rx, &ctrl_c_rx) {
+        let new_game = || editor.start();
+        let mut game = new_game();
+        if run_game(&mut game, false, &input_rx, &ctrl_c_rx, new_game, None) {
+            game.draw_with_messages(&[(10, blink!("GAME OVER!", RED))]);
+            sleep(GAME_OVER_PAUSE);
+            while input_rx.try_recv().is_ok() {}
+        }
+    }
+    mouse_reporting(false);
+}
+
+/// Lets the player change the position until they want to play it, false when they quit.
+fn edit_position(editor: &mut Editor, input_rx: &Receiver<Key>, ctrl_c_rx: &Receiver<()>) -> bool {
+    editor.draw();
+    loop {
+        if check_ctrl_c(ctrl_c_rx) {
+            return false;
+        }
+        match input_rx.recv_timeout(Duration::from_millis(DRAW_SPEED)) {
+            Ok(Key::Char(b'e' | b'\r' | b'\n')) => return true,
+            Ok(key) => {
+                if !editor.edit(key) {
+                    return false;
+                }
+                editor.draw();
+            }
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::garbage::{Batch, GARBAGE};
+use crate::random::Randomizer;
+use crate::replay::{decode_inputs, decode_timing, encode_inputs, encode_timing};
+use crate::rules::Rules;
+use crate::{Block, Clear, Coord, Game, BLOCKS, BOARD_HEIGHT, BOARD_WIDTH};
+
+const SAVE_HEADER: &str = "tetris-save";
+const SAVE_VERSION: u32 = 1;
+
+/// Directory in the user's home where saves and records are kept.
+pub fn data_dir() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
+    Path::new(&home).join(".tetris")
+}
+
+/// Where a game is saved when quitting, and resumed from on the next launch.
+pub fn default_save_path() -> PathBuf {
+    data_dir().join("save")
+}
+
+pub fn save_game(game: &Game, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    fs::write(path, game.snapshot()).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+pub fn load_game(path: &Path) -> Result<Game, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    Game::from_snapshot(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+impl Game {
+    /// Serializes the complete engine state, one `key value` pair per line.
+    /// Puzzles aren't saved, so their block sequence isn't either.
+    pub fn snapshot(&self) -> String {
+        let board = (0..BOARD_HEIGHT)
+            .map(|y| (0..BOARD_WIDTH).map(|x| char::from(b'0' + self.board[x][y])).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut lines = vec![
+            format!("{} {}", SAVE_HEADER, SAVE_VERSION),
+            format!("board {}", board),
+            format!("placed {}", (0..BOARD_HEIGHT)
+                .map(|y| (0..BOARD_WIDTH).map(|x| self.placed[x][y].to_string()).collect::<Vec<_>>().join(","))
+                .collect::<Vec<_>>()
+                .join("/")),
+            format!("cleared_frame {}", self.cleared_frame),
+            format!("block {}", write_block(&self.block)),
+            format!("next {}", write_block(&self.next_block)),
+            format!("hold {}", self.hold_block.as_ref().map_or("-".to_string(), write_block)),
+            format!("hold_used {}", self.hold_used as u8),
+            format!("lines {}", self.lines_cleared),
+            format!("pieces {}", self.pieces),
+            format!("assisted {}", self.assisted as u8),
+            format!("level {}", self.level),
+            format!("score {}", self.score),
+            format!("drop_timer {}", self.drop_timer),
+            format!("lock_timer {}", self.lock_timer),
+            format!("spawn_delay_left {}", self.spawn_delay),
+            format!("countdown {}", self.countdown),
+            format!("clock {}", self.clock),
+            format!("timing {}", encode_timing(&self.timing)),
+            format!("splits {}", self.splits.iter().map(|(millis, score)| format!("{}:{}", millis, score)).collect::<Vec<_>>().join(",")),
+            format!("initial_turns {}", self.initial_turns),
+            format!("initial_hold {}", self.initial_hold as u8),
+            format!("seed {}", self.seed),
+            format!("fixed_seed {}", self.fixed_seed as u8),
+            format!("rng {}", self.rng.state()),
+            format!("garbage_rng {}", self.garbage_rng.state()),
+            format!("frame {}", self.frame),
+            format!("inputs {}", encode_inputs(&self.inputs)),
+            format!("garbage_left {}", self.garbage_left),
+            format!("hole {}", self.hole),
+            format!("last_turned {}", self.last_turned as u8),
+            format!("last_clear {} {} {}", self.last_clear.lines, self.last_clear.tspin as u8, self.last_clear.perfect as u8),
+            format!("target {}", self.target.as_ref().map_or("-".to_string(), write_block)),
+            format!("finesse_keys {}", self.finesse_keys),
+            format!("finesse_faults {}", self.finesse_faults),
+            format!("rise_timer {}", self.rise_timer),
+            format!("incoming {}", self.incoming.iter().map(|batch| format!("{}:{}", batch.rows, batch.ready)).collect::<Vec<_>>().join(",")),
+            format!("combo {}", self.combo),
+            format!("back_to_back {}", self.back_to_back as u8),
+        ];
+        lines.extend(self.rules.fields());
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    pub fn from_snapshot(text: &str) -> Result<Game, String> {
+        let (_, fields) = read_fields(text, SAVE_HEADER, SAVE_VERSION)?;
+
+        let mut game = Game::new(0, Rules::default());
+        game.board = read_board(raw_field(&fields, "board")?)?;
+        game.placed = read_placed(raw_field(&fields, "placed")?)?;
+        game.cleared_frame = field(&fields, "cleared_frame")?;
+        game.block = read_block(raw_field(&fields, "block")?)?;
+        game.next_block = read_block(raw_field(&fields, "next")?)?;
+        game.hold_block = match raw_field(&fields, "hold")? {
+            "-" => None,
+            hold => Some(read_block(hold)?),
+        };
+        game.hold_used = field::<u8>(&fields, "hold_used")? != 0;
+        game.lines_cleared = field(&fields, "lines")?;
+        game.pieces = field(&fields, "pieces")?;
+        game.assisted = field::<u8>(&fields, "assisted")? != 0;
+        game.level = field(&fields, "level")?;
+        game.score = field(&fields, "score")?;
+        game.drop_timer = field(&fields, "drop_timer")?;
+        game.lock_timer = field(&fields, "lock_timer")?;
+        game.spawn_delay = field(&fields, "spawn_delay_left")?;
+        game.countdown = field(&fields, "countdown")?;
+        game.clock = field(&fields, "clock")?;
+        game.splits = read_splits(raw_field(&fields, "splits")?)?;
+        game.timing = decode_timing(raw_field(&fields, "timing")?, game.clock)?;
+        if game.timing.len() as u64 != game.clock {
+            return Err(format!("timing of {} frames for a clock of {}", game.timing.len(), game.clock));
+        }
+        game.millis = game.timing.iter().sum();
+        game.garbage_left = field(&fields, "garbage_left")?;
+        game.hole = field::<usize>(&fields, "hole")?.min(BOARD_WIDTH);
+        game.last_turned = field::<u8>(&fields, "last_turned")? != 0;
+        game.last_clear = read_clear(raw_field(&fields, "last_clear")?)?;
+        game.target = match raw_field(&fields, "target")? {
+            "-" => None,
+            target => Some(read_block(target)?),
+        };
+        game.finesse_keys = field(&fields, "finesse_keys")?;
+        game.finesse_faults = field(&fields, "finesse_faults")?;
+        game.rise_timer = field(&fields, "rise_timer")?;
+        game.incoming = read_incoming(raw_field(&fields, "incoming")?)?;
+        game.combo = field(&fields, "combo")?;
+        game.back_to_back = field::<u8>(&fields, "back_to_back")? != 0;
+        game.initial_turns = field(&fields, "initial_turns")?;
+        game.initial_hold = field::<u8>(&fields, "initial_hold")? != 0;
+        game.rng = Randomizer::from_state(field(&fields, "rng")?);
+        game.seed = field(&fields, "seed")?;
+        game.fixed_seed = field::<u8>(&fields, "fixed_seed")? != 0;
+        game.garbage_rng = Randomizer::from_state(field(&fields, "garbage_rng")?);
+        game.frame = field(&fields, "frame")?;
+        game.inputs = decode_inputs(raw_field(&fields, "inputs")?)?;
+        game.rules = Rules::from_fields(&fields)?;
+        Ok(game)
+    }
+}
+
+/// Checks the `<header> <version>` line and collects the `key value` lines below it,
+/// versions up to `latest` are accepted.
+pub fn read_fields<'a>(text: &'a str, header: &str, latest: u32) -> Result<(u32, HashMap<&'a str, &'a str>), String> {
+    let mut lines = text.lines();
+    let version = match lines.next().unwrap_or_default().split_once(' ') {
+        Some((found, version)) if found == header => match version.parse::<u32>() {
+            Ok(version @ 1..) if version <= latest => version,
+            _ => return Err(format!("unsupported {} version {}", header, version)),
+        },
+        _ => return Err(format!("not a {} file", header)),
+    };
+    let fields = lines
+        .filter_map(|line| line.split_once(' '))
+        .collect();
+    Ok((version, fields))
+}
+
+pub fn raw_field<'a>(fields: &HashMap<&str, &'a str>, key: &str) -> Result<&'a str, String> {
+    fields.get(key).map(|value| value.trim()).ok_or_else(|| format!("missing {}", key))
+}
+
+pub fn field<T: FromStr>(fields: &HashMap<&str, &str>, key: &str) -> Result<T, String> {
+    let value = raw_field(fields, key)?;
+    value.parse().map_err(|_| format!("invalid {}: {}", key, value))
+}
+
+/// A field that may be left out.
+pub fn field_or<T: FromStr>(fields: &HashMap<&str, &str>, key: &str, default: T) -> Result<T, String> {
+    match fields.contains_key(key) {
+        true => field(fields, key),
+        false => Ok(default),
+    }
+}
+
+/// Reads board rows from top to bottom, separated by '/', with a color digit for every cell.
+/// Fewer rows than the board has are the bottom of it.
+pub fn read_board(text: &str) -> Result<[[u8; BOARD_HEIGHT]; BOARD_WIDTH], String> {
+    let rows: Vec<&str> = text.split('/').collect();
+    if rows.len() > BOARD_HEIGHT || rows.iter().any(|row| row.len() != BOARD_WIDTH) {
+        return Err("board has the wrong size".to_string());
+    }
+    let mut board = [[0u8; BOARD_HEIGHT]; BOARD_WIDTH];
+    let top = BOARD_HEIGHT - rows.len();
+    for (y, row) in rows.iter().enumerate() {
+        for (x, cell) in row.bytes().enumerate() {
+            board[x][top + y] = read_color(cell.wrapping_sub(b'0'))?;
+        }
+    }
+    Ok(board)
+}
+
+fn read_placed(text: &str) -> Result<[[u64; BOARD_HEIGHT]; BOARD_WIDTH], String> {
+    let invalid = || format!("invalid placed: {}", text);
+    let rows: Vec<&str> = text.trim().split('/').collect();
+    if rows.len() != BOARD_HEIGHT {
+        return Err(invalid());
+    }
+    let mut placed = [[0; BOARD_HEIGHT]; BOARD_WIDTH];
+    for (y, row) in rows.iter().enumerate() {
+        let frames: Vec<&str> = row.split(',').collect();
+        if frames.len() != BOARD_WIDTH {
+            return Err(invalid());
+        }
+        for (x, frame) in frames.iter().enumerate() {
+            placed[x][y] = frame.parse().map_err(|_| invalid())?;
+        }
+    }
+    Ok(placed)
+}
+
+fn read_clear(text: &str) -> Result<Clear, String> {
+    let invalid = || format!("invalid last_clear: {}", text);
+    let numbers = text.split_whitespace().map(|n| n.parse::<u32>().map_err(|_| invalid())).collect::<Result<Vec<_>, _>>()?;
+    match numbers[..] {
+        [lines, tspin, perfect] => Ok(Clear { lines, tspin: tspin != 0, perfect: perfect != 0 }),
+        _ => Err(invalid()),
+    }
+}
+
+fn write_block(block: &Block) -> String {
+    format!("{} {} {} {} {} {} {} {} {}", block.color as u8,
+            block.a.x, block.a.y, block.b.x, block.b.y, block.c.x, block.c.y, block.d.x, block.d.y)
+}
+
+fn read_block(text: &str) -> Result<Block, String> {
+    let numbers = text.split_whitespace()
+        .map(|n| n.parse::<usize>().map_err(|_| format!("invalid block: {}", text)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if numbers.len() != 9 {
+        return Err(format!("invalid block: {}", text));
+    }
+    let coord = |i: usize| -> Result<Coord, String> {
+        let (x, y) = (numbers[i], numbers[i + 1]);
+        if x >= BOARD_WIDTH || y >= BOARD_HEIGHT {
+            return Err(format!("block outside the board: {}", text));
+        }
+        Ok(Coord { x, y })
+    };
+    let color = read_color(numbers[0] as u8)?;
+    let template = BLOCKS.iter()
+        .find(|b| b.color as u8 == color)
+        .ok_or_else(|| format!("invalid block: {}", text))?;
+    Ok(Block { a: coord(1)?, b: coord(3)?, c: coord(5)?, d: coord(7)?, color: template.color })
+}
+
+fn read_splits(text: &str) -> Result<Vec<(u64, u32)>, String> {
+    text.split(',')
+        .filter(|split| !split.trim().is_empty())
+        .map(|split| {
+            let (millis, score) = split.trim().split_once(':').ok_or_else(|| format!("invalid split: {}", split))?;
+            Ok((millis.parse().map_err(|_| format!("invalid split: {}", split))?,
+                score.parse().map_err(|_| format!("invalid split: {}", split))?))
+        })
+        .collect()
+}
+
+fn read_incoming(text: &str) -> Result<Vec<Batch>, String> {
+    text.split(',')
+        .filter(|batch| !batch.trim().is_empty())
+        .map(|batch| {
+            let (rows, ready) = batch.trim().split_once(':').ok_or_else(|| format!("invalid garbage: {}", batch))?;
+            Ok(Batch {
+                rows: rows.parse().map_err(|_| format!("invalid garbage: {}", batch))?,
+                ready: ready.parse().map_err(|_| format!("invalid garbage: {}", batch))?,
+            })
+        })
+        .collect()
+}
+
+fn read_color(value: u8) -> Result<u8, String> {
+    if BLOCKS.iter().any(|b| b.color as u8 == value) || value == 0 || value == GARBAGE {
+        Ok(value)
+    } else {
+        Err(format!("invalid color {}", value))
+    }
+}
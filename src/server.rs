@@ -0,0 +1,183 @@
+use std::io;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::pacer::Pacer;
+use crate::protocol::listen;
+use crate::rules::Rules;
+use crate::terminal::*;
+use crate::terminal::Color::*;
+use crate::Move::*;
+use crate::{move_for_key, random_seed, Game, Move, DRAW_SPEED};
+
+// telnet commands and the options negotiated with them
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SUBNEGOTIATION: u8 = 250;
+const SUBNEGOTIATION_END: u8 = 240;
+const INTERRUPT: u8 = 244;
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const LINEMODE: u8 = 34;
+// the server echoes nothing, which leaves the client no echo of its own, and takes single keys
+pub const NEGOTIATION: [u8; 9] = [IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD, IAC, DONT, LINEMODE];
+pub const CTRL_C: u8 = 3;
+
+/// Serves a game to everyone connecting with telnet or nc, each player plays a game of their own
+/// with the given rules. Games on the server aren't saved and don't count for records.
+pub fn serve(port: u16, rules: Rules) -> Result<(), String> {
+    let listener = listen(port)?;
+    line_in_color(&format!("Serving games on port {}, play with telnet <host> {}", port, port), YELLOW);
+    for stream in listener.incoming().filter_map(Result::ok) {
+        let address = stream.peer_addr().map_or_else(|_| "someone".to_string(), |address| address.to_string());
+        thread::spawn(move || {
+            line_in_color(&format!("{} connected", address), BLUE);
+            match play_session(stream, rules) {
+                Ok(()) => line_in_color(&format!("{} left", address), BLUE),
+                // closing the connection is how telnet and nc leave as well
+                Err(error) if matches!(error.kind(), ErrorKind::BrokenPipe | ErrorKind::ConnectionReset) => {
+                    line_in_color(&format!("{} left", address), BLUE)
+                }
+                Err(error) => line_in_color(&format!("{} dropped, {}", address, error), RED),
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Bytes a telnet client sent, leaving out its commands, an interrupt comes through as a ctrl-c.
+struct TelnetData<I> {
+    bytes: I,
+}
+
+impl<I: Iterator<Item=u8>> Iterator for TelnetData<I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        loop {
+            let byte = self.bytes.next()?;
+            if byte != IAC {
+                return Some(byte);
+            }
+            match self.bytes.next()? {
+                IAC => return Some(IAC),
+                INTERRUPT => return Some(CTRL_C),
+                WILL | WONT | DO | DONT => {
+                    self.bytes.next()?;
+                }
+                SUBNEGOTIATION => while !(self.bytes.next()? == IAC && self.bytes.next()? == SUBNEGOTIATION_END) {},
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Plays games with a player until they quit or the connection drops, drawing each frame to the
+/// connection in one write.
+fn play_session(mut stream: TcpStream, rules: Rules) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let input_rx = key_channel(stream.try_clone()?);
+    let mut screen = NEGOTIATION.to_vec();
+    clear_screen_to(&mut screen)?;
+    cursor_visible_to(&mut screen, false)?;
+    line_in_color_to(&mut screen, "Welcome to Terminal Tetris!", YELLOW)?;
+    create_game_screen_to(&mut screen, 24)?;
+    stream.write_all(&screen)?;
+
+    let new_game = || Game::new(random_seed(), rules);
+    let mut game = new_game();
+    let mut paused = false;
+    let mut pacer = Pacer::start();
+    loop {
+        let mut screen = Vec::new();
+        if paused {
+            game.render(&mut screen, true, false, &[])?;
+        } else if game.tick() {
+            game.render(&mut screen, false, false, &[])?;
+        } else {
+            game.render(&mut screen, false, true, &[
+                (8, blink!("GAME OVER!", RED)),
+                (10, color!(" r restart", WHITE)),
+                (11, color!(" q quit   ", WHITE)),
+            ])?;
+            stream.write_all(&screen)?;
+            if !wait_for_restart(&input_rx) {
+                break;
+            }
+            game = new_game();
+            pacer.restart();
+            continue;
+        }
+        stream.write_all(&screen)?;
+
+        let Some(moves) = take_moves(&input_rx) else {
+            break;
+        };
+        for mov in moves {
+            match (mov, paused) {
+                (Pause | FocusLost, false) => paused = true,
+                (Pause, true) => paused = false,
+                (Restart, true) => {
+                    game = new_game();
+                    paused = false;
+                }
+                (mov, false) => game.apply(mov),
+                _ => {}
+            }
+        }
+
+        pacer.wait();
+    }
+    let mut screen = Vec::new();
+    cursor_visible_to(&mut screen, true)?;
+    line_in_color_to(&mut screen, "Thanks for playing!", YELLOW)?;
+    stream.write_all(&screen)
+}
+
+/// Reads the keys a player presses on a thread of their own, the channel closes when they disconnect.
+pub fn key_channel(stream: TcpStream) -> Receiver<Key> {
+    let (key_tx, key_rx) = channel();
+    thread::spawn(move || {
+        let mut bytes = TelnetData { bytes: BufReader::new(stream).bytes().map_while(Result::ok) };
+        while let Some(key) = read_key(&mut bytes) {
+            if key_tx.send(key).is_err() {
+                break;
+            }
+        }
+    });
+    key_rx
+}
+
+/// The moves the player made since the last frame, None once they quit or disconnected.
+fn take_moves(input_rx: &Receiver<Key>) -> Option<Vec<Move>> {
+    let mut moves = Vec::new();
+    loop {
+        match input_rx.try_recv() {
+            Ok(Key::Char(CTRL_C)) | Err(TryRecvError::Disconnected) => return None,
+            Ok(key) => match move_for_key(key) {
+                Some(Quit) => return None,
+                Some(mov) => moves.push(mov),
+                None => {}
+            },
+            Err(TryRecvError::Empty) => return Some(moves),
+        }
+    }
+}
+
+/// Waits for the player to play again after game over, false when they leave.
+fn wait_for_restart(input_rx: &Receiver<Key>) -> bool {
+    loop {
+        match take_moves(input_rx) {
+            None => return false,
+            Some(moves) if moves.contains(&Restart) => return true,
+            Some(_) => sleep(Duration::from_millis(DRAW_SPEED)),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::{Game, DRAW_SPEED};
+
+pub const SURVIVAL_MESSINESS: u32 = 30;
+// frames between rows of garbage rising at the start and at the fastest, one frame faster every `SPEEDUP` played
+const SLOWEST_RISE: u64 = 10_000 / DRAW_SPEED;
+const FASTEST_RISE: u64 = 1_500 / DRAW_SPEED;
+const SPEEDUP: u64 = 5_000 / DRAW_SPEED;
+// the coming row flashes for the last second before it rises
+const WARNING: u64 = 1_000 / DRAW_SPEED;
+
+impl Game {
+    fn rise_interval(&self) -> u64 {
+        SLOWEST_RISE.saturating_sub(self.clock / SPEEDUP).max(FASTEST_RISE)
+    }
+
+    /// Frames until the coming row of garbage rises.
+    pub fn rise_left(&self) -> u64 {
+        self.rise_interval().saturating_sub(self.rise_timer)
+    }
+
+    pub fn rise_warning(&self) -> bool {
+        self.rise_left() <= WARNING
+    }
+
+    /// Scores the seconds survived with the lines cleared and raises a row of garbage once it's time,
+    /// its hole is where the coming row shows it. Returns false when the stack was pushed over the top.
+    pub fn survival_frame(&mut self, messiness: u32) -> bool {
+        self.score = (self.time_millis() / 1000) as u32 + self.lines_cleared;
+        self.rise_timer += 1;
+        if self.rise_timer < self.rise_interval() {
+            return true;
+        }
+        self.rise_timer = 0;
+        let hole = self.hole;
+        self.next_hole(messiness);
+        self.raise_garbage(hole)
+    }
+}
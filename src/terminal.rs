@@ -1,8 +1,10 @@
 extern crate core;
 
 use std::arch::asm;
+use std::io;
+use std::io::Write;
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
 #[derive(Copy, Clone)]
 pub enum Color { BLACK, RED, GREEN, YELLOW, BLUE, MAGENTA, CYAN, WHITE, UNKNOWN, DEFAULT }
 
@@ -12,11 +14,31 @@ pub const MOVE_UP: char = 'A';
 pub const MOVE_DOWN: char = 'B';
 pub const MOVE_RIGHT: char = 'C';
 pub const MOVE_LEFT: char = 'D';
+pub const FOCUS_IN: char = 'I';
+pub const FOCUS_OUT: char = 'O';
+// mouse reports in SGR form, `ESC [ < button ; column ; row M` on press and `m` on release
+const MOUSE: char = '<';
+const MOUSE_PRESS: u8 = b'M';
+const ESCAPE: u8 = 0x1b;
 
 const IOCTRL: u64 = 16;
 const IOCTRL_TCGETS: u64 = 0x5401;
 const IOCTRL_TCSETS: u64 = 0x5402;
 
+#[derive(Debug)]
+pub enum Key {
+    Char(u8),
+    Up,
+    Down,
+    Right,
+    Left,
+    FocusIn,
+    FocusOut,
+    // a press or drag with button 0 (left), 1 (middle) or 2 (right) on a cell of the screen, counted from 1
+    Mouse { button: u8, column: usize, row: usize },
+    Unknown,
+}
+
 #[derive(Debug, Default)]
 #[repr(C)]
 struct TermiosState {
@@ -51,7 +73,7 @@ fn sys_tcsets(termios: *const TermiosState) -> i64 {
     unsafe { syscall(IOCTRL, TERMINAL_FD, IOCTRL_TCSETS, termios as u64) }
 }
 
-pub fn do_in_game_terminal(game_fn: fn()) {
+pub fn do_in_game_terminal(game_fn: impl FnOnce()) {
     let mut termios: TermiosState = Default::default();
 
     if sys_tcgets(&termios as *const _) == 0 {
@@ -59,10 +81,12 @@ pub fn do_in_game_terminal(game_fn: fn()) {
         termios.c_lflag &= !0xA; // single key input, no echo of user input
         sys_tcsets(&termios as *const _);
         hide_cursor();
+        focus_reporting(true);
 
         game_fn();
 
         // reset both options
+        focus_reporting(false);
         show_cursor();
         termios.c_lflag = original_flags;
         sys_tcgets(&termios as *const _);
@@ -72,58 +96,141 @@ pub fn do_in_game_terminal(game_fn: fn()) {
 }
 
 
+// the functions writing to a given output draw screens for the server's players as well as the local one,
+// the others write to the local terminal only
+
 pub fn create_game_screen(screen_height: u8) {
+    create_game_screen_to(&mut io::stdout(), screen_height).expect("Cannot write to stdout");
+}
+
+pub fn create_game_screen_to(out: &mut impl Write, screen_height: u8) -> io::Result<()> {
     // write X empty lines, move cursor back to start and save its position
     for _ in 0..screen_height {
-        overwrite();
-        println!();
+        overwrite_to(out)?;
+        write!(out, "\r\n")?;
     }
-    move_cursor_up(screen_height);
-    save_cursor();
+    write!(out, "{}{}{}{}s", CONTROL_SEQ, screen_height, MOVE_UP, CONTROL_SEQ)
+}
+
+pub fn show_cursor() {
+    cursor_visible_to(&mut io::stdout(), true).expect("Cannot write to stdout");
 }
 
-pub fn move_cursor_up(lines: u8) {
-    print!("{}{}{}", CONTROL_SEQ, lines, MOVE_UP);
+pub fn hide_cursor() {
+    cursor_visible_to(&mut io::stdout(), false).expect("Cannot write to stdout");
 }
 
-#[allow(dead_code)]
-pub fn move_cursor_down(lines: u8) {
-    print!("{}{}{}", CONTROL_SEQ, lines, MOVE_DOWN);
+pub fn cursor_visible_to(out: &mut impl Write, visible: bool) -> io::Result<()> {
+    write!(out, "{}?25{}", CONTROL_SEQ, if visible { 'h' } else { 'l' })
 }
 
-#[allow(dead_code)]
-pub fn move_cursor_right(columns: u8) {
-    print!("{}{}{}", CONTROL_SEQ, columns, MOVE_RIGHT);
+/// Makes the terminal report gaining and losing focus as key input.
+pub fn focus_reporting(enabled: bool) {
+    print!("{}?1004{}", CONTROL_SEQ, if enabled { 'h' } else { 'l' });
 }
 
-#[allow(dead_code)]
-pub fn move_cursor_left(columns: u8) {
-    print!("{}{}{}", CONTROL_SEQ, columns, MOVE_LEFT);
+/// Makes the terminal report mouse presses and drags as key input.
+pub fn mouse_reporting(enabled: bool) {
+    let switch = if enabled { 'h' } else { 'l' };
+    print!("{}?1002{}{}?1006{}", CONTROL_SEQ, switch, CONTROL_SEQ, switch);
 }
 
-pub fn show_cursor() {
-    print!("{}?25h", CONTROL_SEQ);
+/// Empties the screen and puts the cursor in its top left corner, for screens that place things
+/// where the mouse can point at them.
+pub fn clear_screen() {
+    clear_screen_to(&mut io::stdout()).expect("Cannot write to stdout");
 }
 
-pub fn hide_cursor() {
-    print!("{}?25l", CONTROL_SEQ);
+pub fn clear_screen_to(out: &mut impl Write) -> io::Result<()> {
+    write!(out, "{}2J{}H", CONTROL_SEQ, CONTROL_SEQ)
 }
 
-pub fn save_cursor() {
-    print!("{}s", CONTROL_SEQ);
+pub fn move_cursor_to(row: usize, column: usize) {
+    print!("{}{};{}H", CONTROL_SEQ, row, column);
 }
 
 pub fn restore_cursor() {
-    print!("{}u", CONTROL_SEQ);
+    restore_cursor_to(&mut io::stdout()).expect("Cannot write to stdout");
+}
+
+pub fn restore_cursor_to(out: &mut impl Write) -> io::Result<()> {
+    write!(out, "{}u", CONTROL_SEQ)
 }
 
 pub fn overwrite() {
-    print!("{}2K\r", CONTROL_SEQ);
+    overwrite_to(&mut io::stdout()).expect("Cannot write to stdout");
+}
+
+pub fn overwrite_to(out: &mut impl Write) -> io::Result<()> {
+    write!(out, "{}2K\r", CONTROL_SEQ)
+}
+
+/// Reads the next key from the input, decoding arrow key, focus and mouse escape sequences.
+/// Returns None once the input is exhausted.
+pub fn read_key(bytes: &mut impl Iterator<Item=u8>) -> Option<Key> {
+    let byte = bytes.next()?;
+    if byte != ESCAPE {
+        return Some(Key::Char(byte));
+    }
+    if bytes.next()? != b'[' {
+        return Some(Key::Unknown);
+    }
+    let key = match bytes.next()? as char {
+        MOVE_UP => Key::Up,
+        MOVE_DOWN => Key::Down,
+        MOVE_RIGHT => Key::Right,
+        MOVE_LEFT => Key::Left,
+        FOCUS_IN => Key::FocusIn,
+        FOCUS_OUT => Key::FocusOut,
+        MOUSE => read_mouse(bytes)?,
+        _ => Key::Unknown,
+    };
+    Some(key)
+}
+
+fn read_mouse(bytes: &mut impl Iterator<Item=u8>) -> Option<Key> {
+    let mut numbers = [0usize; 3];
+    let mut index = 0;
+    loop {
+        match bytes.next()? {
+            digit @ b'0'..=b'9' if index < numbers.len() => {
+                numbers[index] = numbers[index].saturating_mul(10).saturating_add((digit - b'0') as usize);
+            }
+            b';' => index += 1,
+            end => {
+                let [button, column, row] = numbers;
+                // releases and wheel turns don't paint anything
+                if end != MOUSE_PRESS || index != 2 || button & 64 != 0 {
+                    return Some(Key::Unknown);
+                }
+                return Some(Key::Mouse { button: (button & 3) as u8, column, row });
+            }
+        }
+    }
+}
+
+/// Columns a text takes on screen, leaving out the escape sequences coloring it.
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char == ESCAPE as char {
+            // a control sequence ends with its letter
+            chars.find(char::is_ascii_alphabetic);
+        } else {
+            width += 1;
+        }
+    }
+    width
 }
 
 pub fn line_in_color(msg: &str, color: Color) {
-    overwrite();
-    println!("{}", color!(msg, color));
+    line_in_color_to(&mut io::stdout(), msg, color).expect("Cannot write to stdout");
+}
+
+pub fn line_in_color_to(out: &mut impl Write, msg: &str, color: Color) -> io::Result<()> {
+    overwrite_to(out)?;
+    write!(out, "{}\r\n", color!(msg, color))
 }
 
 #[macro_export]
@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use crate::modes::format_time;
+use crate::replay::{load_replay, Frame, Outcome};
+
+/// Plays a replay without a terminal as fast as possible and compares the result with the one
+/// claimed in the file. Returns the exit code: 0 when they agree, 1 otherwise.
+pub fn verify(path: &Path) -> i32 {
+    let replay = match load_replay(path) {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 1;
+        }
+    };
+
+    let mut game = replay.new_game();
+    let mut inputs = replay.inputs.iter().peekable();
+    while let Frame::Played = replay.play_frame(&mut game, &mut inputs) {}
+
+    let outcome = Outcome::of(&game);
+    println!("score  {}", outcome.score);
+    println!("lines  {}", outcome.lines);
+    println!("level  {}", outcome.level);
+    println!("pieces {}", outcome.pieces);
+    println!("time   {}", format_time(outcome.millis));
+    println!("hash   {:016x}", outcome.hash);
+
+    match &replay.result {
+        Some(claimed) if *claimed == outcome => {
+            println!("result matches the replay");
+            if replay.assisted {
+                println!("placements were undone, not eligible for records");
+            }
+            0
+        }
+        Some(claimed) => {
+            eprintln!("result does not match the replay, it claims score {} lines {} level {} pieces {} time {} hash {:016x}",
+                      claimed.score, claimed.lines, claimed.level, claimed.pieces, format_time(claimed.millis), claimed.hash);
+            1
+        }
+        None => {
+            eprintln!("replay claims no result to check");
+            1
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::mem;
+use std::sync::mpsc::Receiver;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::modes::Mode;
+use crate::pacer::Pacer;
+use crate::random::random_seed;
+use crate::rules::Rules;
+use crate::terminal::*;
+use crate::terminal::Color::*;
+use crate::{check_ctrl_c, ctrl_c_channel, input_channel, move_for_key, Game, Move};
+
+pub const VERSUS_ROUNDS: u32 = 3;
+pub const ROUND_PAUSE: Duration = Duration::from_millis(2000);
+// columns the first player's screen takes, the second one's goes next to it
+const SCREEN_WIDTH: usize = 34;
+
+pub enum Round {
+    Won(usize),
+    Draw,
+    Quit,
+}
+
+/// Keys of the players sharing a keyboard, the first one on the letters and the second one on the arrows.
+fn player_move(key: &Key) -> Option<(usize, Move)> {
+    match key {
+        Key::Char(b'w') => Some((0, Move::Up)),
+        Key::Char(b's') => Some((0, Move::Down)),
+        Key::Char(b'd') => Some((0, Move::Right)),
+        Key::Char(b'a') => Some((0, Move::Left)),
+        Key::Char(b'c') => Some((0, Move::Hold)),
+        Key::Up => Some((1, Move::Up)),
+        Key::Down => Some((1, Move::Down)),
+        Key::Right => Some((1, Move::Right)),
+        Key::Left => Some((1, Move::Left)),
+        Key::Char(b'0') => Some((1, Move::Hold)),
+        _ => None,
+    }
+}
+
+/// Plays rounds between two players until one of them won more than half of `rounds`,
+/// both get the same blocks in a round.
+pub fn play_versus(rounds: u32, seed: Option<u64>, rules: Rules) {
+    let input_rx = input_channel(Some);
+    let ctrl_c_rx = ctrl_c_channel();
+    let rules = Rules { mode: Mode::Versus, practice: false, ..rules };
+    let mut wins = [0; 2];
+    line_in_color(&format!("Versus, best of {}", rounds), YELLOW);
+    create_game_screen(24);
+    while wins.iter().all(|won| won * 2 <= rounds) {
+        let seed = seed.unwrap_or_else(random_seed);
+        let mut games = [Game::new(seed, rules), Game::new(seed, rules)];
+        let round = play_round(&mut games, &wins, &input_rx, &ctrl_c_rx);
+        if let Round::Won(winner) = round {
+            wins[winner] += 1;
+        }
+        match round_messages(&round) {
+            Some(messages) => draw_versus(&games, &titles(&wins), false, &messages),
+            None => return,
+        }
+        sleep(ROUND_PAUSE);
+        while input_rx.try_recv().is_ok() {}
+    }
+    let winner = if wins[0] > wins[1] { 1 } else { 2 };
+    line_in_color(&format!("Player {} wins the match {} to {}", winner, wins[0].max(wins[1]), wins[0].min(wins[1])), BLUE);
+}
+
+fn titles(wins: &[u32; 2]) -> [String; 2] {
+    [0, 1].map(|player| format!("Player {}  wins {}", player + 1, wins[player]))
+}
+
+/// What shows over the boards once a round is over, nothing when it was quit.
+pub fn round_messages(round: &Round) -> Option<[Vec<(usize, String)>; 2]> {
+    match round {
+        Round::Quit => None,
+        Round::Draw => Some([vec![(10, blink!("   DRAW   ", YELLOW))], vec![(10, blink!("   DRAW   ", YELLOW))]]),
+        Round::Won(winner) => {
+            let mut messages = [vec![(10, blink!("   K.O.   ", RED))], vec![(10, blink!("   K.O.   ", RED))]];
+            messages[*winner] = vec![(10, blink!("  WINNER  ", GREEN))];
+            Some(messages)
+        }
+    }
+}
+
+/// Plays a round until a player tops out, garbage sent by one player waits to rise on the other's board.
+fn play_round(games: &mut [Game; 2], wins: &[u32; 2], input_rx: &Receiver<Key>, ctrl_c_rx: &Receiver<()>) -> Round {
+    let mut pacer = Pacer::start();
+    let mut paused = false;
+    loop {
+        if !paused {
+            let alive = [games[0].tick(), games[1].tick()];
+            match alive {
+                [true, true] => {}
+                [false, false] => return Round::Draw,
+                [true, false] => return Round::Won(0),
+                [false, true] => return Round::Won(1),
+            }
+            for player in 0..2 {
+                let sent = mem::take(&mut games[player].outgoing);
+                games[1 - player].receive_garbage(sent);
+            }
+        }
+        draw_versus(games, &titles(wins), paused, &[vec![], vec![]]);
+
+        while let Ok(key) = input_rx.try_recv() {
+            if let Some((player, mov)) = player_move(&key) {
+                if !paused {
+                    games[player].apply(mov);
+                }
+                continue;
+            }
+            match (move_for_key(key), paused) {
+                (Some(Move::Pause), _) => paused = !paused,
+                (Some(Move::FocusLost), _) => paused = true,
+                (Some(Move::Restart), true) => {
+                    let seed = games[0].seed;
+                    *games = [Game::new(seed, games[0].rules), Game::new(seed, games[1].rules)];
+                    paused = false;
+                }
+                (Some(Move::Quit), true) => return Round::Quit,
+                _ => {}
+            }
+        }
+        if check_ctrl_c(ctrl_c_rx) {
+            return Round::Quit;
+        }
+
+        pacer.wait();
+    }
+}
+
+/// Draws both boards side by side under their titles, each with its messages over it.
+pub fn draw_versus(games: &[Game; 2], titles: &[String; 2], paused: bool, messages: &[Vec<(usize, String)>; 2]) {
+    restore_cursor();
+    let [left, right] = [0, 1].map(|player| {
+        let mut lines = vec![format!("       {}", color!(titles[player], YELLOW))];
+        lines.extend(games[player].screen(paused, !messages[player].is_empty(), &messages[player]));
+        lines
+    });
+    for (left, right) in left.iter().zip(&right) {
+        overwrite();
+        let padding = SCREEN_WIDTH.saturating_sub(visible_width(left));
+        println!("{}{:padding$}{}", left, "", right);
+    }
+}